@@ -0,0 +1,46 @@
+//! The frame buffer and the flat-field map as the bytes they are stored as,
+//! which is how they are packed, saved and loaded.
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+/// Integers without padding, of which any bytes are a valid value.
+pub trait Word: private::Sealed {}
+impl Word for u16 {}
+impl Word for u32 {}
+
+/// The bytes of `words` in memory order.
+pub fn as_bytes<W: Word>(words: &[W]) -> &[u8] {
+    // SAFETY: `Word`s have no padding, so all their bytes are initialized,
+    // and `u8` has no alignment, so the middle part is all of them.
+    let (_, bytes, _) = unsafe { words.align_to::<u8>() };
+    bytes
+}
+
+/// The bytes of `words` in memory order.
+pub fn as_bytes_mut<W: Word>(words: &mut [W]) -> &mut [u8] {
+    // SAFETY: As for `as_bytes`; any bytes written are also a valid `Word`.
+    let (_, bytes, _) = unsafe { words.align_to_mut::<u8>() };
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_are_in_memory_order() {
+        let mut words = [0x0403_0201u32, 0x0807_0605];
+        assert_eq!(as_bytes(&words).len(), 8);
+        assert_eq!(as_bytes(&words)[..4], 0x0403_0201u32.to_ne_bytes());
+        as_bytes_mut(&mut words)[4..].copy_from_slice(&[0xFF; 4]);
+        assert_eq!(words[1], u32::MAX);
+        assert_eq!(
+            as_bytes(&[1u16, 2]),
+            [1u16.to_ne_bytes(), 2u16.to_ne_bytes()].concat()
+        );
+    }
+}
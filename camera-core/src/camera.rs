@@ -0,0 +1,378 @@
+use embedded_hal::digital::InputPin;
+
+/// Time the shutter button has to be stable before a change is accepted.
+pub const DEBOUNCE_MS: u32 = 20;
+
+/// Holding the shutter button down this long after a press calibrates the
+/// camera once the image of the press is saved.
+pub const LONG_PRESS_MS: u32 = 2_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureKind {
+    /// A picture, saved to the SD card.
+    Image,
+    /// A calibration frame: with manual exposure, a dark frame taken with
+    /// the lens cap on; with auto exposure, evenly lit flat frames.
+    Calibration,
+}
+
+/// Hardware steps the camera state machine drives. Each step is called
+/// exactly once per capture, in the order of the states below.
+pub trait CaptureBackend {
+    type Error;
+
+    /// Prepares the sensor and the frame buffer for a new exposure.
+    fn arm(&mut self, kind: CaptureKind) -> Result<(), Self::Error>;
+
+    /// Exposes and reads out a frame into the frame buffer.
+    fn expose(&mut self) -> Result<(), Self::Error>;
+
+    /// Saves the last exposed frame.
+    fn save(&mut self) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraState {
+    Idle,
+    /// The image of a press is saved and the shutter button is still down,
+    /// but not yet long enough to calibrate.
+    Held,
+    Arming,
+    Exposing,
+    Saving,
+}
+
+/// Debounces an active-low push button sampled at arbitrary intervals.
+pub struct Debouncer {
+    debounce_ms: u32,
+    pressed: bool,
+    candidate: bool,
+    candidate_since_ms: u32,
+}
+
+impl Debouncer {
+    pub const fn new(debounce_ms: u32) -> Self {
+        Self {
+            debounce_ms,
+            pressed: false,
+            candidate: false,
+            candidate_since_ms: 0,
+        }
+    }
+
+    pub const fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Feeds a raw sample taken at `now_ms` and returns `true` only on the
+    /// sample where a press is accepted.
+    pub fn update(&mut self, raw_pressed: bool, now_ms: u32) -> bool {
+        if raw_pressed != self.candidate {
+            self.candidate = raw_pressed;
+            self.candidate_since_ms = now_ms;
+        }
+        if self.candidate != self.pressed
+            && now_ms.wrapping_sub(self.candidate_since_ms) >= self.debounce_ms
+        {
+            self.pressed = self.candidate;
+            return self.pressed;
+        }
+        false
+    }
+}
+
+/// Camera state machine: idle -> arming -> exposing -> saving -> idle.
+///
+/// A debounced press of the shutter button takes an image right away. If the
+/// button is still down once the image is saved, the camera waits in held
+/// until it is released or the long press time since the press has passed,
+/// which calibrates the camera. Holding the button down does not start
+/// another capture until it is released.
+pub struct Camera<B: InputPin> {
+    shutter_button: B,
+    debouncer: Debouncer,
+    long_press_ms: u32,
+    pressed_since_ms: u32,
+    kind: CaptureKind,
+    state: CameraState,
+}
+
+impl<B> Camera<B>
+where
+    B: InputPin,
+{
+    pub const fn new(shutter_button: B, debounce_ms: u32, long_press_ms: u32) -> Self {
+        Self {
+            shutter_button,
+            debouncer: Debouncer::new(debounce_ms),
+            long_press_ms,
+            pressed_since_ms: 0,
+            kind: CaptureKind::Image,
+            state: CameraState::Idle,
+        }
+    }
+
+    pub const fn state(&self) -> CameraState {
+        self.state
+    }
+
+    /// Advances the state machine by one step and returns the new state.
+    ///
+    /// On error the state machine returns to idle, so the next press starts
+    /// a fresh capture.
+    pub fn poll<C: CaptureBackend>(
+        &mut self,
+        backend: &mut C,
+        now_ms: u32,
+    ) -> Result<CameraState, CameraError<C::Error>> {
+        let raw_pressed = self
+            .shutter_button
+            .is_low()
+            .map_err(|_| CameraError::ShutterButton)?;
+        let pressed = self.debouncer.update(raw_pressed, now_ms);
+
+        let result = match self.state {
+            CameraState::Idle if pressed => {
+                self.pressed_since_ms = now_ms;
+                self.kind = CaptureKind::Image;
+                Ok(CameraState::Arming)
+            }
+            CameraState::Idle => Ok(CameraState::Idle),
+            CameraState::Held if !self.debouncer.is_pressed() => Ok(CameraState::Idle),
+            CameraState::Held
+                if now_ms.wrapping_sub(self.pressed_since_ms) >= self.long_press_ms =>
+            {
+                self.kind = CaptureKind::Calibration;
+                Ok(CameraState::Arming)
+            }
+            CameraState::Held => Ok(CameraState::Held),
+            CameraState::Arming => backend.arm(self.kind).map(|_| CameraState::Exposing),
+            CameraState::Exposing => backend.expose().map(|_| CameraState::Saving),
+            CameraState::Saving => backend.save().map(|_| {
+                if self.kind == CaptureKind::Image && self.debouncer.is_pressed() {
+                    CameraState::Held
+                } else {
+                    CameraState::Idle
+                }
+            }),
+        };
+
+        match result {
+            Ok(state) => {
+                self.state = state;
+                Ok(state)
+            }
+            Err(e) => {
+                self.state = CameraState::Idle;
+                Err(CameraError::Backend(e))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CameraError<E> {
+    ShutterButton,
+    Backend(E),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{cell::Cell, convert::Infallible};
+    use std::{rc::Rc, vec, vec::Vec};
+
+    /// Active-low shutter button whose level the test sets.
+    struct Button(Rc<Cell<bool>>);
+
+    impl embedded_hal::digital::ErrorType for Button {
+        type Error = Infallible;
+    }
+
+    impl InputPin for Button {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0.get())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0.get())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Step {
+        Arm(CaptureKind),
+        Expose,
+        Save,
+    }
+
+    #[derive(Default)]
+    struct Backend {
+        steps: Vec<Step>,
+        fail_expose: bool,
+    }
+
+    impl CaptureBackend for Backend {
+        type Error = ();
+
+        fn arm(&mut self, kind: CaptureKind) -> Result<(), ()> {
+            self.steps.push(Step::Arm(kind));
+            Ok(())
+        }
+
+        fn expose(&mut self) -> Result<(), ()> {
+            self.steps.push(Step::Expose);
+            if self.fail_expose { Err(()) } else { Ok(()) }
+        }
+
+        fn save(&mut self) -> Result<(), ()> {
+            self.steps.push(Step::Save);
+            Ok(())
+        }
+    }
+
+    fn camera() -> (Camera<Button>, Rc<Cell<bool>>) {
+        let pressed = Rc::new(Cell::new(false));
+        let camera = Camera::new(Button(pressed.clone()), DEBOUNCE_MS, LONG_PRESS_MS);
+        (camera, pressed)
+    }
+
+    fn poll_until(
+        camera: &mut Camera<Button>,
+        backend: &mut Backend,
+        from_ms: u32,
+        to_ms: u32,
+    ) -> CameraState {
+        for now_ms in from_ms..to_ms {
+            camera.poll(backend, now_ms).unwrap();
+        }
+        camera.state()
+    }
+
+    #[test]
+    fn debouncer_accepts_stable_press_once() {
+        let mut debouncer = Debouncer::new(DEBOUNCE_MS);
+        assert!(!debouncer.update(true, 0));
+        assert!(!debouncer.update(true, DEBOUNCE_MS - 1));
+        assert!(debouncer.update(true, DEBOUNCE_MS));
+        assert!(debouncer.is_pressed());
+        assert!(!debouncer.update(true, DEBOUNCE_MS + 1));
+    }
+
+    #[test]
+    fn debouncer_ignores_bounces() {
+        let mut debouncer = Debouncer::new(DEBOUNCE_MS);
+        for now_ms in 0..100 {
+            assert!(!debouncer.update(now_ms % 7 < 3, now_ms));
+        }
+        assert!(!debouncer.is_pressed());
+
+        assert!(debouncer.update(true, 200));
+        assert!(!debouncer.update(false, 205));
+        assert!(debouncer.is_pressed());
+        assert!(!debouncer.update(false, 225));
+        assert!(!debouncer.is_pressed());
+    }
+
+    #[test]
+    fn debouncer_survives_timer_wrap() {
+        let mut debouncer = Debouncer::new(DEBOUNCE_MS);
+        assert!(!debouncer.update(true, u32::MAX - 5));
+        assert!(debouncer.update(true, DEBOUNCE_MS - 6));
+    }
+
+    #[test]
+    fn press_takes_image_without_waiting_for_release() {
+        let (mut camera, pressed) = camera();
+        let mut backend = Backend::default();
+        assert_eq!(
+            poll_until(&mut camera, &mut backend, 0, 10),
+            CameraState::Idle
+        );
+
+        pressed.set(true);
+        assert_eq!(
+            poll_until(&mut camera, &mut backend, 10, 30),
+            CameraState::Idle
+        );
+        assert_eq!(camera.poll(&mut backend, 30).unwrap(), CameraState::Arming);
+        assert_eq!(
+            poll_until(&mut camera, &mut backend, 31, 34),
+            CameraState::Held
+        );
+        assert_eq!(
+            backend.steps,
+            vec![Step::Arm(CaptureKind::Image), Step::Expose, Step::Save]
+        );
+
+        pressed.set(false);
+        assert_eq!(
+            poll_until(&mut camera, &mut backend, 34, 100),
+            CameraState::Idle
+        );
+        assert_eq!(backend.steps.len(), 3);
+    }
+
+    #[test]
+    fn hold_calibrates_after_image() {
+        let (mut camera, pressed) = camera();
+        let mut backend = Backend::default();
+        pressed.set(true);
+        assert_eq!(
+            poll_until(&mut camera, &mut backend, 0, 24),
+            CameraState::Held
+        );
+        backend.steps.clear();
+
+        let long_press_ms = DEBOUNCE_MS + LONG_PRESS_MS;
+        assert_eq!(
+            poll_until(&mut camera, &mut backend, 24, long_press_ms),
+            CameraState::Held
+        );
+        assert!(backend.steps.is_empty());
+        assert_eq!(
+            poll_until(&mut camera, &mut backend, long_press_ms, long_press_ms + 4),
+            CameraState::Idle
+        );
+        assert_eq!(
+            backend.steps,
+            vec![
+                Step::Arm(CaptureKind::Calibration),
+                Step::Expose,
+                Step::Save
+            ]
+        );
+
+        // Still holding the button does not start another capture.
+        assert_eq!(
+            poll_until(
+                &mut camera,
+                &mut backend,
+                long_press_ms + 4,
+                3 * LONG_PRESS_MS
+            ),
+            CameraState::Idle
+        );
+        assert_eq!(backend.steps.len(), 3);
+    }
+
+    #[test]
+    fn backend_error_returns_to_idle() {
+        let (mut camera, pressed) = camera();
+        let mut backend = Backend {
+            fail_expose: true,
+            ..Backend::default()
+        };
+        pressed.set(true);
+        poll_until(&mut camera, &mut backend, 0, 22);
+        assert!(matches!(
+            camera.poll(&mut backend, 22),
+            Err(CameraError::Backend(()))
+        ));
+        assert_eq!(camera.state(), CameraState::Idle);
+        assert_eq!(
+            poll_until(&mut camera, &mut backend, 23, 3000),
+            CameraState::Idle
+        );
+    }
+}
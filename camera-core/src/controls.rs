@@ -0,0 +1,228 @@
+use crate::{
+    exposure::{Exposure, Gain},
+    metering::MeteringMode,
+};
+
+/// Largest value returned by the 12-bit ADC.
+pub const ADC_FULL_SCALE: u16 = 4095;
+
+/// ADC counts a dial has to move past a stop boundary before the selection
+/// changes, so a dial resting on a boundary does not flicker between stops.
+pub const HYSTERESIS: u16 = 32;
+
+/// Shutter speeds selectable with the SHUTTER_SPEED dial, from the fastest to
+/// the slowest.
+pub const SHUTTER_SPEEDS: [Exposure; 11] = [
+    seconds(1, 1000),
+    seconds(1, 500),
+    seconds(1, 250),
+    seconds(1, 125),
+    seconds(1, 60),
+    seconds(1, 30),
+    seconds(1, 15),
+    seconds(1, 8),
+    seconds(1, 4),
+    seconds(1, 2),
+    seconds(1, 1),
+];
+
+/// Analog gains selectable with the GAIN dial in half stops. All of them are
+/// exactly representable by the MT9M001 global gain register.
+pub const GAINS: [Gain; 9] = [
+    gain(1.0),
+    gain(1.5),
+    gain(2.0),
+    gain(3.0),
+    gain(4.0),
+    gain(6.0),
+    gain(8.0),
+    gain(12.0),
+    gain(15.0),
+];
+
+const fn seconds(numerator: u32, denominator: u32) -> Exposure {
+    match Exposure::from_fraction(numerator, denominator) {
+        Ok(exposure) => exposure,
+        Err(_) => panic!("invalid shutter speed"),
+    }
+}
+
+const fn gain(gain: f32) -> Gain {
+    match Gain::new(gain) {
+        Ok(gain) => gain,
+        Err(_) => panic!("invalid gain"),
+    }
+}
+
+/// Metering modes selectable with the GAIN dial while the SHUTTER_SPEED dial
+/// is turned past the slowest shutter speed to the auto exposure position.
+pub const METERING_MODES: [MeteringMode; 3] = [
+    MeteringMode::Average,
+    MeteringMode::CenterWeighted,
+    MeteringMode::Spot,
+];
+
+/// Raw dial readings as returned by the ADC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DialPositions {
+    pub shutter_speed: u16,
+    pub gain: u16,
+}
+
+pub trait DialInputs {
+    type Error;
+
+    fn sample(&mut self) -> Result<DialPositions, Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExposureSettings {
+    pub exposure: Exposure,
+    pub gain: Gain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExposureMode {
+    Manual(ExposureSettings),
+    Auto(MeteringMode),
+}
+
+/// Maps a raw ADC reading onto one of `steps` equally wide zones. The
+/// `current` zone is kept until the reading leaves it by more than
+/// [`HYSTERESIS`] counts.
+pub fn quantize(raw: u16, steps: usize, current: usize) -> usize {
+    let span = ADC_FULL_SCALE as u32 + 1;
+    let steps = steps as u32;
+    let raw = raw.min(ADC_FULL_SCALE) as u32;
+    let current = (current as u32).min(steps - 1);
+
+    let lower = (current * span / steps).saturating_sub(HYSTERESIS as u32);
+    let upper = (current + 1) * span / steps + HYSTERESIS as u32;
+    if (lower..upper).contains(&raw) {
+        current as usize
+    } else {
+        (raw * steps / span) as usize
+    }
+}
+
+/// Tracks the selected stop of both dials.
+#[derive(Default)]
+pub struct Controls {
+    shutter_speed: usize,
+    gain: usize,
+    metering_mode: usize,
+}
+
+impl Controls {
+    pub const fn new() -> Self {
+        Self {
+            shutter_speed: 0,
+            gain: 0,
+            metering_mode: 0,
+        }
+    }
+
+    pub fn update(&mut self, dials: DialPositions) -> ExposureMode {
+        // One more position than there are shutter speeds for auto exposure.
+        self.shutter_speed = quantize(
+            dials.shutter_speed,
+            SHUTTER_SPEEDS.len() + 1,
+            self.shutter_speed,
+        );
+        self.gain = quantize(dials.gain, GAINS.len(), self.gain);
+        self.metering_mode = quantize(dials.gain, METERING_MODES.len(), self.metering_mode);
+        self.mode()
+    }
+
+    pub const fn mode(&self) -> ExposureMode {
+        if self.shutter_speed == SHUTTER_SPEEDS.len() {
+            ExposureMode::Auto(METERING_MODES[self.metering_mode])
+        } else {
+            ExposureMode::Manual(ExposureSettings {
+                exposure: SHUTTER_SPEEDS[self.shutter_speed],
+                gain: GAINS[self.gain],
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantize_splits_range_into_equal_zones() {
+        assert_eq!(quantize(0, 4, 0), 0);
+        assert_eq!(quantize(1023 + HYSTERESIS + 1, 4, 0), 1);
+        assert_eq!(quantize(2048, 4, 3), 2);
+        assert_eq!(quantize(ADC_FULL_SCALE, 4, 0), 3);
+        assert_eq!(quantize(u16::MAX, 4, 0), 3);
+    }
+
+    #[test]
+    fn quantize_keeps_stop_within_hysteresis() {
+        // The boundary between zone 0 and 1 of 4 is at 1024.
+        assert_eq!(quantize(1024 + HYSTERESIS - 1, 4, 0), 0);
+        assert_eq!(quantize(1024 + HYSTERESIS, 4, 0), 1);
+        assert_eq!(quantize(1024 - HYSTERESIS, 4, 1), 1);
+        assert_eq!(quantize(1024 - HYSTERESIS - 1, 4, 1), 0);
+    }
+
+    #[test]
+    fn quantize_clamps_out_of_range_stop() {
+        assert_eq!(quantize(ADC_FULL_SCALE, 4, 10), 3);
+        assert_eq!(quantize(0, 4, 10), 0);
+    }
+
+    #[test]
+    fn stop_tables_are_ordered() {
+        assert!(
+            SHUTTER_SPEEDS
+                .windows(2)
+                .all(|w| w[0].as_micros() < w[1].as_micros())
+        );
+        assert_eq!(SHUTTER_SPEEDS[0].as_micros(), 1_000);
+        assert_eq!(
+            SHUTTER_SPEEDS[SHUTTER_SPEEDS.len() - 1].as_micros(),
+            1_000_000
+        );
+        assert!(GAINS.windows(2).all(|w| w[0].register() < w[1].register()));
+    }
+
+    #[test]
+    fn gains_are_exact() {
+        let expected = [1.0, 1.5, 2.0, 3.0, 4.0, 6.0, 8.0, 12.0, 15.0];
+        for (gain, expected) in GAINS.iter().zip(expected) {
+            assert_eq!(gain.as_f32(), expected);
+            assert_eq!(Gain::from_register(gain.register()), *gain);
+        }
+    }
+
+    #[test]
+    fn slowest_position_selects_auto_exposure() {
+        let mut controls = Controls::new();
+        let mode = controls.update(DialPositions {
+            shutter_speed: 0,
+            gain: ADC_FULL_SCALE,
+        });
+        assert_eq!(
+            mode,
+            ExposureMode::Manual(ExposureSettings {
+                exposure: SHUTTER_SPEEDS[0],
+                gain: GAINS[GAINS.len() - 1],
+            })
+        );
+
+        let mode = controls.update(DialPositions {
+            shutter_speed: ADC_FULL_SCALE,
+            gain: 0,
+        });
+        assert_eq!(mode, ExposureMode::Auto(METERING_MODES[0]));
+
+        let mode = controls.update(DialPositions {
+            shutter_speed: ADC_FULL_SCALE,
+            gain: ADC_FULL_SCALE,
+        });
+        assert_eq!(mode, ExposureMode::Auto(MeteringMode::Spot));
+    }
+}
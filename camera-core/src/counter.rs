@@ -0,0 +1,192 @@
+//! The image counter kept in the F-RAM.
+//!
+//! The counter is stored twice, each copy with a CRC-32. The counter only
+//! ever grows by one, so its value doubles as the sequence number of a copy.
+//! Every increment overwrites the older copy, so a brown-out during a write
+//! leaves the newer copy intact. F-RAM writes do not wear the memory out,
+//! so the copies can stay where they are.
+
+use crate::{
+    crc::crc32,
+    platform::{PersistentMemory, RECORD_PROTECTION},
+};
+
+const COPIES: [u16; 2] = [0x0700, 0x0710];
+const _: () = assert!(COPIES[0] >= RECORD_PROTECTION.start());
+const COPY_BYTES: usize = 12;
+/// Where older firmware kept the counter, as a bare little-endian u64.
+const LEGACY_ADDRESS: u16 = 0;
+
+pub struct FrameCounter {
+    /// The next image number.
+    next: u64,
+    /// The copy written last, which the next increment leaves alone.
+    copy: Option<usize>,
+}
+
+impl FrameCounter {
+    /// Recovers the counter from the newest valid copy. If the other copy
+    /// is damaged, it may have held a number already given out, so that one
+    /// is skipped and the damaged copy is the next to be overwritten.
+    /// Without any valid copy, the counter of older firmware is taken over.
+    pub fn load<M: PersistentMemory>(memory: &mut M) -> Result<Self, CounterError> {
+        let mut values = [None; 2];
+        for (value, address) in values.iter_mut().zip(COPIES) {
+            let mut bytes = [0; COPY_BYTES];
+            memory
+                .read(address, &mut bytes)
+                .map_err(|_| CounterError::Fram)?;
+            *value = parse_copy(&bytes);
+        }
+
+        let counter = match values {
+            [Some(a), Some(b)] if a >= b => Self {
+                next: a,
+                copy: Some(0),
+            },
+            [Some(_), Some(b)] => Self {
+                next: b,
+                copy: Some(1),
+            },
+            [Some(a), None] => Self {
+                next: a.checked_add(1).ok_or(CounterError::Exhausted)?,
+                copy: Some(0),
+            },
+            [None, Some(b)] => Self {
+                next: b.checked_add(1).ok_or(CounterError::Exhausted)?,
+                copy: Some(1),
+            },
+            [None, None] => {
+                let mut legacy = [0; 8];
+                memory
+                    .read(LEGACY_ADDRESS, &mut legacy)
+                    .map_err(|_| CounterError::Fram)?;
+                let legacy = u64::from_le_bytes(legacy);
+                Self {
+                    // A blank chip reads all zeros, or all ones if it was
+                    // erased that way; both start counting at 0.
+                    next: if legacy == u64::MAX { 0 } else { legacy },
+                    copy: None,
+                }
+            }
+        };
+        Ok(counter)
+    }
+
+    /// The image number `take` returns next.
+    pub const fn next(&self) -> u64 {
+        self.next
+    }
+
+    /// Reserves the next image number. It is stored as used before it is
+    /// returned, so it is never given out twice.
+    pub fn take<M: PersistentMemory>(&mut self, memory: &mut M) -> Result<u64, CounterError> {
+        let number = self.next;
+        let next = number.checked_add(1).ok_or(CounterError::Exhausted)?;
+        let copy = match self.copy {
+            Some(copy) => 1 - copy,
+            None => 0,
+        };
+
+        let mut bytes = [0; COPY_BYTES];
+        bytes[..8].copy_from_slice(&next.to_le_bytes());
+        let crc = crc32(&bytes[..8]);
+        bytes[8..].copy_from_slice(&crc.to_le_bytes());
+        memory
+            .write(COPIES[copy], &bytes)
+            .map_err(|_| CounterError::Fram)?;
+
+        self.next = next;
+        self.copy = Some(copy);
+        Ok(number)
+    }
+}
+
+fn parse_copy(bytes: &[u8; COPY_BYTES]) -> Option<u64> {
+    let (value, crc) = bytes.split_at(8);
+    if crc32(value).to_le_bytes() != crc {
+        return None;
+    }
+    Some(u64::from_le_bytes([
+        value[0], value[1], value[2], value[3], value[4], value[5], value[6], value[7],
+    ]))
+}
+
+#[derive(Debug)]
+pub enum CounterError {
+    Fram,
+    /// The counter cannot grow any further.
+    Exhausted,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{platform::Fram, sim::SimTimer};
+    use fm25l16b::{FM25L16B, sim::Simulator};
+
+    fn memory(chip: &mut Simulator) -> Fram<&mut Simulator, SimTimer> {
+        Fram::new(FM25L16B::new(chip), SimTimer::default())
+    }
+
+    fn load(chip: &mut Simulator) -> FrameCounter {
+        FrameCounter::load(&mut memory(chip)).unwrap()
+    }
+
+    fn take(chip: &mut Simulator, counter: &mut FrameCounter) -> u64 {
+        counter.take(&mut memory(chip)).unwrap()
+    }
+
+    #[test]
+    fn blank_chip_starts_at_zero() {
+        for erased in [0x00, 0xFF] {
+            let mut chip = Simulator::new();
+            chip.memory.fill(erased);
+            let mut counter = load(&mut chip);
+            assert_eq!(counter.next(), 0);
+            assert_eq!(take(&mut chip, &mut counter), 0);
+        }
+    }
+
+    #[test]
+    fn numbers_are_not_given_out_twice() {
+        let mut chip = Simulator::new();
+        let mut counter = load(&mut chip);
+        for number in 0..5 {
+            assert_eq!(take(&mut chip, &mut counter), number);
+            assert_eq!(counter.copy, Some(number as usize % 2));
+        }
+        let mut counter = load(&mut chip);
+        assert_eq!(take(&mut chip, &mut counter), 5);
+        assert_eq!(counter.copy, Some(1));
+    }
+
+    #[test]
+    fn interrupted_increment_skips_a_number() {
+        let mut chip = Simulator::new();
+        let mut counter = load(&mut chip);
+        take(&mut chip, &mut counter);
+        take(&mut chip, &mut counter);
+        chip.write_budget = Some(COPY_BYTES / 2);
+        take(&mut chip, &mut counter);
+        chip.write_budget = None;
+
+        // The torn copy may have given out 2, so 3 comes next and the torn
+        // copy is overwritten first.
+        let mut counter = load(&mut chip);
+        assert_eq!(counter.next(), 3);
+        assert_eq!(take(&mut chip, &mut counter), 3);
+        assert_eq!(counter.copy, Some(0));
+        assert_eq!(load(&mut chip).next(), 4);
+    }
+
+    #[test]
+    fn legacy_counter_is_taken_over() {
+        let mut chip = Simulator::new();
+        chip.memory[..8].copy_from_slice(&1234u64.to_le_bytes());
+        let mut counter = load(&mut chip);
+        assert_eq!(take(&mut chip, &mut counter), 1234);
+        assert_eq!(take(&mut chip, &mut counter), 1235);
+        assert_eq!(load(&mut chip).next(), 1236);
+    }
+}
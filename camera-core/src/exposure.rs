@@ -0,0 +1,264 @@
+//! Integration time and analog gain as the MT9M001 registers represent them.
+
+/// Largest value of the 14-bit Shutter Width register.
+pub const MAX_SHUTTER_WIDTH: u16 = mt9m001::ShutterWidth::MAX;
+
+/// The sensor ignores Horizontal Blanking values below this.
+const MIN_HORIZONTAL_BLANKING: u16 = 19;
+
+/// Pixel clock periods of a row which are not integrated.
+const OVERHEAD: u32 = 180;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+/// The registers which determine the row time and thus the integration time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowTiming {
+    pub column_size: u16,
+    pub horizontal_blanking: u16,
+    pub shutter_delay: u16,
+}
+
+impl RowTiming {
+    /// Column skip 8 shortens the row time as if the window was narrower.
+    pub const fn column_skip_8(self) -> Self {
+        Self {
+            column_size: (self.column_size / 16) * 2 + 1,
+            ..self
+        }
+    }
+
+    /// Row time in pixel clock periods.
+    pub const fn row_time(&self) -> u32 {
+        let horizontal_blanking = if self.horizontal_blanking < MIN_HORIZONTAL_BLANKING {
+            MIN_HORIZONTAL_BLANKING
+        } else {
+            self.horizontal_blanking
+        };
+        (self.column_size as u32 + 1) + 244 + (horizontal_blanking - MIN_HORIZONTAL_BLANKING) as u32
+    }
+
+    /// Pixel clock periods of the first row which are not integrated.
+    pub const fn overhead(&self) -> u32 {
+        OVERHEAD + 4 * self.shutter_delay as u32
+    }
+
+    /// Integration time in microseconds achieved by a Shutter Width register
+    /// value.
+    pub const fn integration_us(&self, shutter_width: u16, frequency: u32) -> u32 {
+        let clocks =
+            (shutter_width as u64 * self.row_time() as u64).saturating_sub(self.overhead() as u64);
+        (clocks * 1_000_000 / frequency as u64) as u32
+    }
+}
+
+/// Integration time in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exposure {
+    us: u32,
+}
+
+impl Exposure {
+    pub const fn from_micros(us: u32) -> Result<Self, OutOfRange> {
+        if us == 0 {
+            return Err(OutOfRange);
+        }
+        Ok(Self { us })
+    }
+
+    /// Creates an exposure of `numerator / denominator` seconds.
+    pub const fn from_fraction(numerator: u32, denominator: u32) -> Result<Self, OutOfRange> {
+        if denominator == 0 {
+            return Err(OutOfRange);
+        }
+        let us = numerator as u64 * 1_000_000 / denominator as u64;
+        if us > u32::MAX as u64 {
+            return Err(OutOfRange);
+        }
+        Self::from_micros(us as u32)
+    }
+
+    pub const fn as_micros(&self) -> u32 {
+        self.us
+    }
+
+    /// Returns the Shutter Width register value which comes closest to this
+    /// exposure.
+    pub const fn shutter_width(
+        &self,
+        timing: &RowTiming,
+        frequency: u32,
+    ) -> Result<u16, OutOfRange> {
+        let clocks = self.us as u64 * frequency as u64 / 1_000_000;
+        let row_time = timing.row_time() as u64;
+        let rows = (clocks + timing.overhead() as u64 + row_time / 2) / row_time;
+        if rows == 0 || rows > MAX_SHUTTER_WIDTH as u64 {
+            return Err(OutOfRange);
+        }
+        Ok(rows as u16)
+    }
+}
+
+/// Analog gain as programmed into the gain registers.
+///
+/// Gains up to 4x are set in 1/8 steps, up to 8x in 1/4 steps (using the
+/// doubling bit 6, which has less noise than high multipliers), and above
+/// that in whole steps up to 15x.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gain {
+    register: u16,
+}
+
+impl Gain {
+    pub const UNITY: Self = Self { register: 0x0008 };
+    pub const MIN: f32 = 1.0;
+    pub const MAX: f32 = 15.0;
+
+    /// Returns the representable gain nearest to `gain`.
+    pub const fn new(gain: f32) -> Result<Self, OutOfRange> {
+        if !(gain >= Self::MIN && gain <= Self::MAX) {
+            return Err(OutOfRange);
+        }
+        let register = if gain <= 4.0 {
+            (gain * 8.0 + 0.5) as u16
+        } else if gain <= 8.0 {
+            0x0040 | (gain * 4.0 + 0.5) as u16
+        } else {
+            0x0060 | (gain - 8.0 + 0.5) as u16
+        };
+        Ok(Self { register })
+    }
+
+    /// The gain a gain register holds.
+    pub const fn from_register(register: u16) -> Self {
+        Self { register }
+    }
+
+    pub const fn register(&self) -> u16 {
+        self.register
+    }
+
+    /// The gain in multiples of 1/8.
+    pub const fn eighths(&self) -> u16 {
+        match self.register {
+            0x0060..=0x0067 => (8 + (self.register & 0x0007)) * 8,
+            0x0040..=0x005F => (self.register & 0x003F) * 2,
+            _ => self.register & 0x003F,
+        }
+    }
+
+    /// ISO speed reported in the image metadata. The MT9M001 has no rated
+    /// sensitivity, so unity gain is nominally ISO 100.
+    pub const fn iso(&self) -> u16 {
+        self.eighths() * 100 / 8
+    }
+
+    pub const fn as_f32(&self) -> f32 {
+        self.eighths() as f32 / 8.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FREQUENCY: u32 = 6_500_000;
+    const TIMING: RowTiming = RowTiming {
+        column_size: 1311,
+        horizontal_blanking: MIN_HORIZONTAL_BLANKING,
+        shutter_delay: 0,
+    };
+
+    #[test]
+    fn row_time() {
+        assert_eq!(TIMING.row_time(), 1312 + 244);
+        let blanked = RowTiming {
+            horizontal_blanking: MIN_HORIZONTAL_BLANKING + 100,
+            ..TIMING
+        };
+        assert_eq!(blanked.row_time(), TIMING.row_time() + 100);
+        let below_minimum = RowTiming {
+            horizontal_blanking: 0,
+            ..TIMING
+        };
+        assert_eq!(below_minimum.row_time(), TIMING.row_time());
+        assert_eq!(TIMING.column_skip_8().row_time(), 164 + 244);
+    }
+
+    #[test]
+    fn shutter_width_round_trip() {
+        let half_row_us = TIMING.row_time() * 1_000_000 / FREQUENCY / 2 + 1;
+        for us in [1_000, 2_000, 4_000, 10_000, 33_333, 125_000, 1_000_000] {
+            let exposure = Exposure::from_micros(us).unwrap();
+            let shutter_width = exposure.shutter_width(&TIMING, FREQUENCY).unwrap();
+            let integration_us = TIMING.integration_us(shutter_width, FREQUENCY);
+            assert!(integration_us.abs_diff(us) <= half_row_us, "{us} us");
+        }
+    }
+
+    #[test]
+    fn shutter_width_counts_overhead() {
+        let exposure = Exposure::from_micros(10_000).unwrap();
+        assert_eq!(exposure.shutter_width(&TIMING, FREQUENCY), Ok(42));
+        let delayed = RowTiming {
+            shutter_delay: 400,
+            ..TIMING
+        };
+        assert_eq!(exposure.shutter_width(&delayed, FREQUENCY), Ok(43));
+    }
+
+    #[test]
+    fn shutter_width_out_of_range() {
+        let shortest = Exposure::from_micros(1).unwrap();
+        assert_eq!(shortest.shutter_width(&TIMING, FREQUENCY), Err(OutOfRange));
+        let longest = Exposure::from_fraction(10, 1).unwrap();
+        assert_eq!(longest.shutter_width(&TIMING, FREQUENCY), Err(OutOfRange));
+    }
+
+    #[test]
+    fn exposure_from_fraction() {
+        assert_eq!(Exposure::from_fraction(1, 60).unwrap().as_micros(), 16_666);
+        assert_eq!(Exposure::from_fraction(1, 0), Err(OutOfRange));
+        assert_eq!(Exposure::from_fraction(1, 2_000_000), Err(OutOfRange));
+        assert_eq!(Exposure::from_fraction(5_000, 1), Err(OutOfRange));
+        assert_eq!(Exposure::from_micros(0), Err(OutOfRange));
+    }
+
+    #[test]
+    fn gain_registers() {
+        assert_eq!(Gain::new(1.0), Ok(Gain::UNITY));
+        assert_eq!(Gain::new(1.5).unwrap().register(), 0x000C);
+        assert_eq!(Gain::new(4.0).unwrap().register(), 0x0020);
+        assert_eq!(Gain::new(6.0).unwrap().register(), 0x0058);
+        assert_eq!(Gain::new(12.0).unwrap().register(), 0x0064);
+        assert_eq!(Gain::new(15.0).unwrap().register(), 0x0067);
+        assert_eq!(Gain::UNITY.iso(), 100);
+        assert_eq!(Gain::new(15.0).unwrap().iso(), 1500);
+    }
+
+    #[test]
+    fn gain_out_of_range() {
+        assert_eq!(Gain::new(0.5), Err(OutOfRange));
+        assert_eq!(Gain::new(15.5), Err(OutOfRange));
+        assert_eq!(Gain::new(f32::NAN), Err(OutOfRange));
+    }
+
+    #[test]
+    fn gain_round_trip() {
+        for eighths in 8..=120u16 {
+            let wanted = eighths as f32 / 8.0;
+            let gain = Gain::new(wanted).unwrap();
+            let step = if wanted <= 4.0 {
+                0.125
+            } else if wanted <= 8.0 {
+                0.25
+            } else {
+                1.0
+            };
+            assert!((gain.as_f32() - wanted).abs() <= step / 2.0, "{wanted}");
+            assert_eq!(Gain::new(gain.as_f32()), Ok(gain));
+            assert_eq!(Gain::from_register(gain.register()), gain);
+        }
+    }
+}
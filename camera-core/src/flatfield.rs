@@ -0,0 +1,327 @@
+//! Flat-field (PRNU) correction: per-pixel or per-tile gains which even out
+//! how differently the pixels respond to the same light.
+//!
+//! A map is a sequence of little-endian u16 words: the magic `MKFF`, the
+//! version, the covered area of the readout (top, left, bottom, right), the
+//! tile size in pixels and then one gain per tile, row by row. Gains are
+//! fixed point with `GAIN_SHIFT` fractional bits. The host tool in
+//! `flatfield-tool` writes the same format.
+
+use crate::{
+    geometry::Area,
+    metering::WHITE_LEVEL,
+    packed::{self, GROUP_BYTES, GROUP_PIXELS},
+};
+
+pub const FILE_NAME: &str = "FLAT.FFM";
+const MAGIC: [u16; 2] = [u16::from_le_bytes(*b"MK"), u16::from_le_bytes(*b"FF")];
+const VERSION: u16 = 1;
+pub const HEADER_WORDS: usize = 8;
+pub const GAIN_SHIFT: u32 = 12;
+pub const UNITY: u16 = 1 << GAIN_SHIFT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// The part of the readout the map covers. It has to be group aligned.
+    pub area: Area,
+    pub tile: u16,
+}
+
+impl Header {
+    pub const fn columns(&self) -> usize {
+        self.area.width().div_ceil(self.tile) as usize
+    }
+
+    pub const fn rows(&self) -> usize {
+        self.area.height().div_ceil(self.tile) as usize
+    }
+
+    pub const fn tiles(&self) -> usize {
+        self.columns() * self.rows()
+    }
+
+    pub const fn to_words(self) -> [u16; HEADER_WORDS] {
+        let Area {
+            top,
+            left,
+            bottom,
+            right,
+        } = self.area;
+        [
+            MAGIC[0], MAGIC[1], VERSION, top, left, bottom, right, self.tile,
+        ]
+    }
+
+    const fn tile_index(&self, x: u16, y: u16) -> usize {
+        let column = ((x - self.area.left) / self.tile) as usize;
+        let row = ((y - self.area.top) / self.tile) as usize;
+        row * self.columns() + column
+    }
+}
+
+pub struct FlatField<'a> {
+    header: Header,
+    gains: &'a [u16],
+    /// Pixels per row of the frames the map is applied to.
+    frame_width: u16,
+}
+
+impl<'a> FlatField<'a> {
+    /// Parses a map stored as words for frames of `frame_width` pixels per
+    /// row. Maps reaching beyond that width were calibrated for another
+    /// readout.
+    pub fn parse(words: &'a [u16], frame_width: u16) -> Result<Self, FlatFieldError> {
+        let (header, gains) = words
+            .split_at_checked(HEADER_WORDS)
+            .ok_or(FlatFieldError::Size)?;
+        if header[..2] != MAGIC {
+            return Err(FlatFieldError::Magic);
+        }
+        if header[2] != VERSION {
+            return Err(FlatFieldError::Version);
+        }
+        let header = Header {
+            area: Area {
+                top: header[3],
+                left: header[4],
+                bottom: header[5],
+                right: header[6],
+            },
+            tile: header[7],
+        };
+        if header.tile == 0
+            || header.area.bottom < header.area.top
+            || header.area.right < header.area.left
+            || header.area.right > frame_width
+            || !header.area.is_group_aligned()
+        {
+            return Err(FlatFieldError::Header);
+        }
+        let gains = gains.get(..header.tiles()).ok_or(FlatFieldError::Size)?;
+        Ok(Self {
+            header,
+            gains,
+            frame_width,
+        })
+    }
+
+    /// Applies the gains to the signal above `black_level` of a packed
+    /// readout `frame` in place, clamping at the white level. The gains were
+    /// calibrated without the black level, so scaling it would shade the
+    /// shadows. Pixels outside the map are left alone.
+    pub fn apply(&self, frame: &mut [u8], black_level: u16) {
+        let area = self.header.area;
+        for y in area.top..area.bottom {
+            let first = y as usize * self.frame_width as usize + area.left as usize;
+            let start = first / GROUP_PIXELS * GROUP_BYTES;
+            let len = area.width() as usize / GROUP_PIXELS * GROUP_BYTES;
+            let Some(row) = frame.get_mut(start..start + len) else {
+                return;
+            };
+            for (i, group) in row.chunks_exact_mut(GROUP_BYTES).enumerate() {
+                let x = area.left + (i * GROUP_PIXELS) as u16;
+                let mut pixels = packed::unpack([group[0], group[1], group[2], group[3], group[4]]);
+                for (dx, pixel) in pixels.iter_mut().enumerate() {
+                    let gain = self.gains[self.header.tile_index(x + dx as u16, y)] as u32;
+                    let signal = pixel.saturating_sub(black_level) as u32;
+                    let corrected = (signal * gain + (UNITY as u32 / 2)) >> GAIN_SHIFT;
+                    let corrected = corrected + black_level.min(*pixel) as u32;
+                    *pixel = corrected.min(WHITE_LEVEL as u32) as u16;
+                }
+                group.copy_from_slice(&packed::pack(pixels));
+            }
+        }
+    }
+}
+
+/// Sums evenly lit frames per tile to calibrate a map on the camera.
+pub struct Accumulator<const N: usize> {
+    header: Header,
+    sums: [u32; N],
+}
+
+impl<const N: usize> Accumulator<N> {
+    pub const fn new(header: Header) -> Self {
+        assert!(header.tiles() == N);
+        Self {
+            header,
+            sums: [0; N],
+        }
+    }
+
+    pub const fn header(&self) -> Header {
+        self.header
+    }
+
+    pub fn clear(&mut self) {
+        self.sums = [0; N];
+    }
+
+    /// Adds a packed readout `frame` less `black_level`. The black level has
+    /// to be taken off, or the gains would scale it along with the signal.
+    pub fn add(&mut self, frame: &[u8], frame_width: u16, black_level: u16) {
+        let area = self.header.area;
+        for y in area.top..area.bottom {
+            let first = y as usize * frame_width as usize + area.left as usize;
+            let start = first / GROUP_PIXELS * GROUP_BYTES;
+            let len = area.width() as usize / GROUP_PIXELS * GROUP_BYTES;
+            let Some(row) = frame.get(start..start + len) else {
+                return;
+            };
+            for (i, pixel) in packed::pixels(row).enumerate() {
+                let x = area.left + i as u16;
+                self.sums[self.header.tile_index(x, y)] += pixel.saturating_sub(black_level) as u32;
+            }
+        }
+    }
+
+    /// Writes the gains which bring every tile to the mean of all of them.
+    pub fn gains(&self, gains: &mut [u16]) {
+        let area = self.header.area;
+        let tile = self.header.tile as u32;
+        let pixels = |index: usize| {
+            let column = (index % self.header.columns()) as u32;
+            let row = (index / self.header.columns()) as u32;
+            let width = tile.min(area.width() as u32 - column * tile);
+            let height = tile.min(area.height() as u32 - row * tile);
+            (width * height) as u64
+        };
+
+        let total: u64 = self.sums.iter().map(|sum| *sum as u64).sum();
+        let mean = total as f32 / (area.width() as u32 * area.height() as u32) as f32;
+        for (index, (gain, sum)) in gains.iter_mut().zip(self.sums).enumerate() {
+            let tile_mean = sum as f32 / pixels(index) as f32;
+            *gain = if tile_mean > 0.0 {
+                (mean / tile_mean * UNITY as f32 + 0.5).min(u16::MAX as f32) as u16
+            } else {
+                UNITY
+            };
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FlatFieldError {
+    Magic,
+    Version,
+    Header,
+    Size,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{vec, vec::Vec};
+
+    /// Two 4x2 tiles side by side at the top left corner of an 8x2 frame.
+    const HEADER: Header = Header {
+        area: Area {
+            top: 0,
+            left: 0,
+            bottom: 2,
+            right: 8,
+        },
+        tile: 4,
+    };
+
+    fn frame(left: u16, right: u16) -> Vec<u8> {
+        let groups = [pack4(left), pack4(right)];
+        groups.iter().chain(&groups).flatten().copied().collect()
+    }
+
+    fn pack4(value: u16) -> [u8; GROUP_BYTES] {
+        packed::pack([value; GROUP_PIXELS])
+    }
+
+    #[test]
+    fn gains_even_out_signal_above_black_level() {
+        let mut accumulator = Accumulator::<2>::new(HEADER);
+        // The right tile responds twice as strongly to the same light.
+        accumulator.add(&frame(200, 300), 8, 100);
+        accumulator.add(&frame(200, 300), 8, 100);
+        let mut gains = [0; 2];
+        accumulator.gains(&mut gains);
+        assert_eq!(gains, [UNITY * 3 / 2, UNITY * 3 / 4]);
+    }
+
+    #[test]
+    fn black_level_clamps_at_zero() {
+        let mut accumulator = Accumulator::<2>::new(HEADER);
+        accumulator.add(&frame(50, 300), 8, 100);
+        let mut gains = [0; 2];
+        accumulator.gains(&mut gains);
+        assert_eq!(gains[0], UNITY);
+        assert_eq!(gains[1], UNITY / 2);
+    }
+
+    #[test]
+    fn parse_and_apply() {
+        let mut words = HEADER.to_words().to_vec();
+        words.extend([UNITY * 2, UNITY / 2]);
+        let flat_field = FlatField::parse(&words, 8).unwrap();
+        let mut frame = frame(600, 300);
+        flat_field.apply(&mut frame, 0);
+        assert_eq!(
+            packed::pixels(&frame).collect::<Vec<_>>(),
+            [[WHITE_LEVEL; 4], [150; 4], [WHITE_LEVEL; 4], [150; 4]].concat()
+        );
+    }
+
+    #[test]
+    fn apply_keeps_the_black_level() {
+        let mut accumulator = Accumulator::<2>::new(HEADER);
+        accumulator.add(&frame(200, 300), 8, 100);
+        let mut words = HEADER.to_words().to_vec();
+        words.extend([0; 2]);
+        accumulator.gains(&mut words[HEADER_WORDS..]);
+        let flat_field = FlatField::parse(&words, 8).unwrap();
+
+        let mut flat = frame(200, 300);
+        flat_field.apply(&mut flat, 100);
+        assert!(packed::pixels(&flat).all(|pixel| pixel == 250));
+        // Pixels below the black level are left there.
+        let mut dark = frame(90, 100);
+        flat_field.apply(&mut dark, 100);
+        assert_eq!(
+            packed::pixels(&dark).collect::<Vec<_>>(),
+            [[90; 4], [100; 4], [90; 4], [100; 4]].concat()
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_maps() {
+        let words = HEADER.to_words();
+        assert!(matches!(
+            FlatField::parse(&words, 8),
+            Err(FlatFieldError::Size)
+        ));
+        let mut bad_magic = vec![0; HEADER_WORDS + 2];
+        bad_magic[..HEADER_WORDS].copy_from_slice(&words);
+        bad_magic[0] = 0;
+        assert!(matches!(
+            FlatField::parse(&bad_magic, 8),
+            Err(FlatFieldError::Magic)
+        ));
+        let unaligned = Header {
+            area: Area {
+                left: 2,
+                ..HEADER.area
+            },
+            ..HEADER
+        };
+        let mut unaligned = unaligned.to_words().to_vec();
+        unaligned.extend([UNITY; 2]);
+        assert!(matches!(
+            FlatField::parse(&unaligned, 8),
+            Err(FlatFieldError::Header)
+        ));
+        // Calibrated for wider frames, it would wrap into the next row.
+        let mut words = HEADER.to_words().to_vec();
+        words.extend([UNITY; 2]);
+        assert!(FlatField::parse(&words, 8).is_ok());
+        assert!(matches!(
+            FlatField::parse(&words, 4),
+            Err(FlatFieldError::Header)
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+//! Where the image pixels are in a full frame read out of the sensor.
+//!
+//! Full frames are read out from row and column 0, so besides the active
+//! pixel array they contain the dark rows and columns around it and two
+//! padding columns which make every row fill whole 32-bit words.
+
+use crate::{packed::GROUP_PIXELS, sensor};
+
+/// A rectangle in frame pixels, given by its edges like DNG does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub top: u16,
+    pub left: u16,
+    pub bottom: u16,
+    pub right: u16,
+}
+
+impl Area {
+    pub const fn width(&self) -> u16 {
+        self.right - self.left
+    }
+
+    pub const fn height(&self) -> u16 {
+        self.bottom - self.top
+    }
+
+    /// Returns this area relative to the top left corner of `outer`.
+    pub const fn within(&self, outer: &Area) -> Self {
+        Self {
+            top: self.top - outer.top,
+            left: self.left - outer.left,
+            bottom: self.bottom - outer.top,
+            right: self.right - outer.left,
+        }
+    }
+
+    /// Packed rows can only be cut between groups of 4 pixels.
+    pub const fn is_group_aligned(&self) -> bool {
+        (self.left as usize).is_multiple_of(GROUP_PIXELS)
+            && (self.right as usize).is_multiple_of(GROUP_PIXELS)
+    }
+}
+
+/// Everything the frame buffer holds.
+pub const READOUT: Area = Area {
+    top: 0,
+    left: 0,
+    bottom: sensor::HEIGHT,
+    right: sensor::WIDTH + 2,
+};
+
+/// The active pixel array, which starts where the default Row Start and
+/// Column Start registers point to.
+pub const ACTIVE: Area = Area {
+    top: 12,
+    left: 20,
+    bottom: 12 + 1024,
+    right: 20 + 1280,
+};
+
+const _: () = assert!(READOUT.is_group_aligned() && ACTIVE.is_group_aligned());
+
+/// What happens to the pixels outside the active area when a frame is saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Only the active area is saved.
+    Crop,
+    /// The whole readout is saved and the active area is recorded in the
+    /// ActiveArea and DefaultCrop tags.
+    Keep,
+}
+
+impl Framing {
+    /// The part of the readout which is saved.
+    pub const fn saved_area(self) -> Area {
+        match self {
+            Framing::Crop => ACTIVE,
+            Framing::Keep => READOUT,
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! The camera without its board: the capture state machine, exposure math,
+//! calibration, file naming and the save pipeline.
+//!
+//! The board is reached through the traits in `platform`, implemented by the
+//! firmware for the RP2350 and, with the `sim` feature, by a simulated
+//! camera which runs on the host.
+
+#![no_std]
+
+#[cfg(any(test, feature = "sim"))]
+extern crate std;
+
+mod buffer;
+pub mod camera;
+pub mod controls;
+pub mod counter;
+pub mod crc;
+pub mod exposure;
+pub mod flatfield;
+pub mod geometry;
+pub mod metering;
+pub mod naming;
+pub mod packed;
+pub mod parity;
+pub mod pipeline;
+pub mod platform;
+pub mod sensor;
+pub mod settings;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+pub mod tiff;
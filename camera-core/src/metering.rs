@@ -0,0 +1,349 @@
+//! Auto exposure metering on low resolution preview frames.
+
+use crate::packed;
+
+/// Largest 10-bit pixel value.
+pub const WHITE_LEVEL: u16 = 1023;
+pub const HISTOGRAM_BINS: usize = 64;
+const BIN_WIDTH: u16 = (WHITE_LEVEL + 1) / HISTOGRAM_BINS as u16;
+
+/// Pixels inside the metering region count this many times in the
+/// center-weighted mode.
+pub const CENTER_WEIGHT: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeteringMode {
+    /// Every pixel of the frame has the same weight.
+    Average,
+    /// The whole frame is metered, but the region is weighted more.
+    CenterWeighted,
+    /// Only the region is metered.
+    Spot,
+}
+
+/// A rectangle in preview frame coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Region {
+    /// Returns a region in the middle of the frame covering `percent` of its
+    /// width and height.
+    pub const fn centered(frame_width: u16, frame_height: u16, percent: u16) -> Self {
+        let width = (frame_width as u32 * percent as u32 / 100) as u16;
+        let height = (frame_height as u32 * percent as u32 / 100) as u16;
+        Self {
+            x: (frame_width - width) / 2,
+            y: (frame_height - height) / 2,
+            width,
+            height,
+        }
+    }
+
+    pub const fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    pub bins: [u32; HISTOGRAM_BINS],
+    pub total: u32,
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            bins: [0; HISTOGRAM_BINS],
+            total: 0,
+        }
+    }
+
+    pub fn add(&mut self, value: u16, weight: u32) {
+        let bin = (value.min(WHITE_LEVEL) / BIN_WIDTH) as usize;
+        self.bins[bin] += weight;
+        self.total += weight;
+    }
+
+    /// Returns the upper edge of the bin below which `permille` of the
+    /// weighted pixels fall.
+    pub fn percentile(&self, permille: u32) -> u16 {
+        let threshold = (self.total as u64 * permille as u64).div_ceil(1000);
+        let mut sum = 0u64;
+        for (bin, count) in self.bins.iter().enumerate() {
+            sum += *count as u64;
+            if sum >= threshold {
+                return (bin as u16 + 1) * BIN_WIDTH - 1;
+            }
+        }
+        WHITE_LEVEL
+    }
+
+    /// Share of the weighted pixels, in permille, in the topmost bin.
+    pub fn clipped_permille(&self) -> u32 {
+        if self.total == 0 {
+            return 0;
+        }
+        (self.bins[HISTOGRAM_BINS - 1] as u64 * 1000 / self.total as u64) as u32
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Measurement {
+    /// Weighted mean pixel value.
+    pub mean: u16,
+    pub histogram: Histogram,
+}
+
+/// Meters a packed 10-bit frame of `width` x `height` pixels.
+pub fn measure(
+    frame: &[u8],
+    width: u16,
+    height: u16,
+    mode: MeteringMode,
+    region: Region,
+) -> Measurement {
+    let mut histogram = Histogram::new();
+    let mut sum = 0u64;
+
+    let pixels = packed::pixels(frame).take(width as usize * height as usize);
+    for (i, value) in pixels.enumerate() {
+        let x = (i % width as usize) as u16;
+        let y = (i / width as usize) as u16;
+        let inside = region.contains(x, y);
+        let weight = match mode {
+            MeteringMode::Average => 1,
+            MeteringMode::CenterWeighted if inside => CENTER_WEIGHT,
+            MeteringMode::CenterWeighted => 1,
+            MeteringMode::Spot if inside => 1,
+            MeteringMode::Spot => 0,
+        };
+        if weight > 0 {
+            histogram.add(value, weight);
+            sum += value as u64 * weight as u64;
+        }
+    }
+
+    let mean = if histogram.total > 0 {
+        (sum / histogram.total as u64) as u16
+    } else {
+        0
+    };
+    Measurement { mean, histogram }
+}
+
+/// Integration time and analog gain chosen by the auto exposure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Solution {
+    pub exposure_us: u32,
+    pub gain: f32,
+    /// `false` if the measurement was too clipped or too dark to be trusted,
+    /// in which case another preview with the returned settings is needed.
+    pub converged: bool,
+}
+
+pub struct AutoExposure {
+    pub mode: MeteringMode,
+    pub region: Region,
+    /// Desired weighted mean pixel value.
+    pub target: u16,
+    /// The highlights at this percentile are kept below the white level.
+    pub highlight_permille: u32,
+    /// Longest integration time before the gain is raised.
+    pub max_handheld_us: u32,
+    pub min_exposure_us: u32,
+    pub max_exposure_us: u32,
+    pub max_gain: f32,
+}
+
+impl AutoExposure {
+    /// Solves for the settings which would bring a frame metered as
+    /// `measurement` at `exposure_us` and `gain` onto the target.
+    pub fn solve(&self, measurement: &Measurement, exposure_us: u32, gain: f32) -> Solution {
+        let current = exposure_us as f32 * gain;
+
+        let clipped = measurement.histogram.clipped_permille() > 1000 - self.highlight_permille;
+        let (scale, converged) = if clipped {
+            // Too many pixels are clipped to tell how bright the scene is:
+            // back off three stops and meter again.
+            (0.125, false)
+        } else if measurement.mean == 0 {
+            (8.0, false)
+        } else {
+            let scale = self.target as f32 / measurement.mean as f32;
+            let highlight = measurement.histogram.percentile(self.highlight_permille);
+            let highlight_scale = WHITE_LEVEL as f32 / highlight.max(1) as f32;
+            let scale = scale.min(highlight_scale);
+            (scale, (0.5..=2.0).contains(&scale))
+        };
+
+        let wanted = current * scale;
+        let (exposure_us, gain) = if wanted <= self.max_handheld_us as f32 {
+            (wanted, 1.0)
+        } else if wanted <= self.max_handheld_us as f32 * self.max_gain {
+            (
+                self.max_handheld_us as f32,
+                wanted / self.max_handheld_us as f32,
+            )
+        } else {
+            (wanted / self.max_gain, self.max_gain)
+        };
+
+        let exposure_us = (exposure_us as u32).clamp(self.min_exposure_us, self.max_exposure_us);
+        Solution {
+            exposure_us,
+            gain,
+            converged,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const WIDTH: u16 = 16;
+    const HEIGHT: u16 = 8;
+
+    fn frame(pixel: impl Fn(u16, u16) -> u16) -> Vec<u8> {
+        let pixels: Vec<u16> = (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .map(|(x, y)| pixel(x, y))
+            .collect();
+        pixels
+            .chunks_exact(packed::GROUP_PIXELS)
+            .flat_map(|group| packed::pack([group[0], group[1], group[2], group[3]]))
+            .collect()
+    }
+
+    fn auto_exposure() -> AutoExposure {
+        AutoExposure {
+            mode: MeteringMode::Average,
+            region: Region::centered(WIDTH, HEIGHT, 50),
+            target: 184,
+            highlight_permille: 990,
+            max_handheld_us: 1_000_000 / 30,
+            min_exposure_us: 100,
+            max_exposure_us: 1_000_000,
+            max_gain: 15.0,
+        }
+    }
+
+    fn uniform(value: u16) -> Measurement {
+        let region = Region::centered(WIDTH, HEIGHT, 50);
+        measure(
+            &frame(|_, _| value),
+            WIDTH,
+            HEIGHT,
+            MeteringMode::Average,
+            region,
+        )
+    }
+
+    #[test]
+    fn centered_region() {
+        let region = Region::centered(WIDTH, HEIGHT, 50);
+        assert_eq!(
+            region,
+            Region {
+                x: 4,
+                y: 2,
+                width: 8,
+                height: 4
+            }
+        );
+        assert!(region.contains(4, 2));
+        assert!(region.contains(11, 5));
+        assert!(!region.contains(12, 5));
+        assert!(!region.contains(3, 2));
+    }
+
+    #[test]
+    fn uniform_frame() {
+        let measurement = uniform(500);
+        assert_eq!(measurement.mean, 500);
+        assert_eq!(measurement.histogram.total, WIDTH as u32 * HEIGHT as u32);
+        assert_eq!(measurement.histogram.percentile(500), 511);
+        assert_eq!(measurement.histogram.clipped_permille(), 0);
+        assert_eq!(uniform(WHITE_LEVEL).histogram.clipped_permille(), 1000);
+    }
+
+    #[test]
+    fn modes_weight_the_region() {
+        let region = Region::centered(WIDTH, HEIGHT, 50);
+        let bright_center = frame(|x, y| if region.contains(x, y) { 900 } else { 100 });
+        let mean = |mode| measure(&bright_center, WIDTH, HEIGHT, mode, region).mean;
+
+        // A quarter of the pixels is inside the region.
+        assert_eq!(mean(MeteringMode::Average), 300);
+        assert_eq!(mean(MeteringMode::CenterWeighted), (4 * 900 + 3 * 100) / 7);
+        assert_eq!(mean(MeteringMode::Spot), 900);
+
+        let spot = measure(&bright_center, WIDTH, HEIGHT, MeteringMode::Spot, region);
+        assert_eq!(spot.histogram.total, 32);
+    }
+
+    #[test]
+    fn trailing_pixels_are_ignored() {
+        let region = Region::centered(WIDTH, HEIGHT - 1, 50);
+        let frame = frame(|_, y| if y == HEIGHT - 1 { WHITE_LEVEL } else { 200 });
+        let measurement = measure(&frame, WIDTH, HEIGHT - 1, MeteringMode::Average, region);
+        assert_eq!(measurement.mean, 200);
+    }
+
+    #[test]
+    fn solve_scales_to_target() {
+        let solution = auto_exposure().solve(&uniform(92), 10_000, 1.0);
+        assert_eq!(solution.exposure_us, 20_000);
+        assert_eq!(solution.gain, 1.0);
+        assert!(solution.converged);
+    }
+
+    #[test]
+    fn solve_raises_gain_past_handheld_limit() {
+        let solution = auto_exposure().solve(&uniform(46), 10_000, 1.0);
+        assert_eq!(solution.exposure_us, 1_000_000 / 30);
+        assert!((solution.gain - 1.2).abs() < 0.01);
+        assert!(!solution.converged);
+    }
+
+    #[test]
+    fn solve_backs_off_when_clipped() {
+        let solution = auto_exposure().solve(&uniform(WHITE_LEVEL), 8_000, 1.0);
+        assert_eq!(solution.exposure_us, 1_000);
+        assert!(!solution.converged);
+    }
+
+    #[test]
+    fn solve_opens_up_when_black() {
+        let solution = auto_exposure().solve(&uniform(0), 1_000, 1.0);
+        assert_eq!(solution.exposure_us, 8_000);
+        assert!(!solution.converged);
+    }
+
+    #[test]
+    fn solve_keeps_highlights() {
+        // Mostly dark, but a bright eighth which would clip at the target.
+        let frame = frame(|_, y| if y == 0 { 800 } else { 40 });
+        let region = Region::centered(WIDTH, HEIGHT, 50);
+        let measurement = measure(&frame, WIDTH, HEIGHT, MeteringMode::Average, region);
+        let solution = auto_exposure().solve(&measurement, 10_000, 1.0);
+        let highlight = measurement.histogram.percentile(990);
+        assert_eq!(highlight, 815);
+        assert_eq!(
+            solution.exposure_us,
+            (10_000.0 * WHITE_LEVEL as f32 / highlight as f32) as u32
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! How saved images are named.
+
+use crate::tiff::FileFormat;
+
+/// The highest image number a file name can hold.
+pub const MAX_IMAGE_NUMBER: u64 = 99_999_999;
+/// Images with a number beyond it go into directories of this many each.
+const IMAGES_PER_DIRECTORY: u64 = 100_000;
+const MAX_NAME_BYTES: usize = 17;
+
+/// How saved images are named: a two character prefix followed by the image
+/// number, like `IM00042.DNG`.
+///
+/// The first 100,000 images are saved in the root directory. The later ones
+/// go into a directory for every 100,000 images, named after the prefix and
+/// the number of the directory, like `IM012/IM00042.DNG` for image
+/// 1,200,042.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileNaming {
+    pub prefix: [u8; 2],
+}
+
+impl FileNaming {
+    /// Returns `None` unless the prefix is upper case letters and digits.
+    pub const fn new(prefix: [u8; 2]) -> Option<Self> {
+        let [a, b] = prefix;
+        if !(a.is_ascii_uppercase() || a.is_ascii_digit())
+            || !(b.is_ascii_uppercase() || b.is_ascii_digit())
+        {
+            return None;
+        }
+        Some(Self { prefix })
+    }
+
+    /// Returns the path of image `number` saved as `format`, or `None` if
+    /// the number is beyond `MAX_IMAGE_NUMBER`.
+    pub fn file_name(&self, number: u64, format: &FileFormat) -> Option<FileName> {
+        if number > MAX_IMAGE_NUMBER {
+            return None;
+        }
+        let mut name = FileName {
+            bytes: [0; MAX_NAME_BYTES],
+            len: 0,
+        };
+        let directory = number / IMAGES_PER_DIRECTORY;
+        if directory > 0 {
+            name.push(&self.prefix);
+            name.push_digits(directory, 3);
+            name.push(b"/");
+        }
+        name.push(&self.prefix);
+        name.push_digits(number % IMAGES_PER_DIRECTORY, 5);
+        name.push(match format {
+            FileFormat::Tiff => b".TIF",
+            FileFormat::Dng(_) => b".DNG",
+        });
+        Some(name)
+    }
+}
+
+/// A file name, preceded by the directory it is in for later images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileName {
+    bytes: [u8; MAX_NAME_BYTES],
+    len: usize,
+}
+
+impl FileName {
+    /// The ASCII characters of the name.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    fn push_digits(&mut self, mut number: u64, digits: usize) {
+        for b in self.bytes[self.len..self.len + digits].iter_mut().rev() {
+            *b = b'0' + (number % 10) as u8;
+            number /= 10;
+        }
+        self.len += digits;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiff::Dng;
+
+    const DNG: FileFormat = FileFormat::Dng(Dng {
+        black_level: 0,
+        linearization_table: None,
+    });
+
+    fn name(number: u64, format: &FileFormat) -> Option<std::string::String> {
+        let naming = FileNaming::new(*b"IM").unwrap();
+        let name = naming.file_name(number, format)?;
+        Some(std::str::from_utf8(name.as_bytes()).unwrap().into())
+    }
+
+    #[test]
+    fn first_images_are_in_the_root_directory() {
+        assert_eq!(name(0, &FileFormat::Tiff).unwrap(), "IM00000.TIF");
+        assert_eq!(name(42, &DNG).unwrap(), "IM00042.DNG");
+        assert_eq!(name(99_999, &DNG).unwrap(), "IM99999.DNG");
+    }
+
+    #[test]
+    fn later_images_are_in_directories() {
+        assert_eq!(name(100_000, &DNG).unwrap(), "IM001/IM00000.DNG");
+        assert_eq!(
+            name(1_200_042, &FileFormat::Tiff).unwrap(),
+            "IM012/IM00042.TIF"
+        );
+        assert_eq!(name(MAX_IMAGE_NUMBER, &DNG).unwrap(), "IM999/IM99999.DNG");
+        assert_eq!(name(MAX_IMAGE_NUMBER + 1, &DNG), None);
+    }
+
+    #[test]
+    fn prefix_is_upper_case_letters_and_digits() {
+        assert!(FileNaming::new(*b"A7").is_some());
+        assert!(FileNaming::new(*b"im").is_none());
+        assert!(FileNaming::new(*b"I/").is_none());
+    }
+}
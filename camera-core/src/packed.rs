@@ -0,0 +1,121 @@
+//! The pixel stream written by `main.pio` into the PSRAM frame buffer.
+//!
+//! Pixels are shifted in most significant bit first, so every 5 bytes hold
+//! 4 pixels: `aaaaaaaa aabbbbbb bbbbcccc ccccccdd dddddddd`. This is also the
+//! layout TIFF expects for `BitsPerSample = 10`.
+
+pub const GROUP_BYTES: usize = 5;
+pub const GROUP_PIXELS: usize = 4;
+
+pub const fn unpack(group: [u8; GROUP_BYTES]) -> [u16; GROUP_PIXELS] {
+    let [b0, b1, b2, b3, b4] = group;
+    [
+        ((b0 as u16) << 2) | ((b1 as u16) >> 6),
+        (((b1 & 0x3F) as u16) << 4) | ((b2 as u16) >> 4),
+        (((b2 & 0x0F) as u16) << 6) | ((b3 as u16) >> 2),
+        (((b3 & 0x03) as u16) << 8) | b4 as u16,
+    ]
+}
+
+pub const fn pack(pixels: [u16; GROUP_PIXELS]) -> [u8; GROUP_BYTES] {
+    let [a, b, c, d] = pixels;
+    [
+        (a >> 2) as u8,
+        ((a & 0x03) << 6) as u8 | (b >> 4) as u8,
+        ((b & 0x0F) << 4) as u8 | (c >> 6) as u8,
+        ((c & 0x3F) << 2) as u8 | (d >> 8) as u8,
+        d as u8,
+    ]
+}
+
+/// Iterates over the pixels of a packed buffer. Trailing bytes that do not
+/// form a whole group are ignored.
+pub fn pixels(packed: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    packed
+        .chunks_exact(GROUP_BYTES)
+        .flat_map(|group| unpack([group[0], group[1], group[2], group[3], group[4]]))
+}
+
+/// Subtracts a packed `dark` frame from a packed `frame` in place, clamping
+/// at zero.
+pub fn subtract(frame: &mut [u8], dark: &[u8]) {
+    let groups = frame
+        .chunks_exact_mut(GROUP_BYTES)
+        .zip(dark.chunks_exact(GROUP_BYTES));
+    for (group, dark) in groups {
+        let [a, b, c, d] = unpack([group[0], group[1], group[2], group[3], group[4]]);
+        let [da, db, dc, dd] = unpack([dark[0], dark[1], dark[2], dark[3], dark[4]]);
+        let pixels = [
+            a.saturating_sub(da),
+            b.saturating_sub(db),
+            c.saturating_sub(dc),
+            d.saturating_sub(dd),
+        ];
+        group.copy_from_slice(&pack(pixels));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn packed(pixels: &[u16]) -> Vec<u8> {
+        pixels
+            .chunks_exact(GROUP_PIXELS)
+            .flat_map(|group| pack([group[0], group[1], group[2], group[3]]))
+            .collect()
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        for value in 0..1024u16 {
+            let pixels = [
+                value,
+                1023 - value,
+                value ^ 0x2AA,
+                value.rotate_left(3) & 0x3FF,
+            ];
+            assert_eq!(unpack(pack(pixels)), pixels);
+        }
+        assert_eq!(pack([0x3FF, 0, 0, 0]), [0xFF, 0xC0, 0, 0, 0]);
+        assert_eq!(pack([0, 0, 0, 0x3FF]), [0, 0, 0, 0x03, 0xFF]);
+    }
+
+    #[test]
+    fn pixels_ignore_trailing_bytes() {
+        let mut frame = packed(&[1, 2, 3, 4]);
+        frame.extend_from_slice(&[0xFF; 4]);
+        assert_eq!(pixels(&frame).collect::<Vec<_>>(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn subtract_dark_frame() {
+        let mut frame = packed(&[100, 1023, 64, 500, 0, 37, 900, 1]);
+        let dark = packed(&[36, 23, 64, 1, 0, 36, 0, 1]);
+        subtract(&mut frame, &dark);
+        assert_eq!(
+            pixels(&frame).collect::<Vec<_>>(),
+            [64, 1000, 0, 499, 0, 1, 900, 0]
+        );
+    }
+
+    #[test]
+    fn subtract_clamps_at_zero() {
+        let mut frame = packed(&[10, 0, 1022, 5]);
+        let dark = packed(&[11, 1023, 1023, 5]);
+        subtract(&mut frame, &dark);
+        assert_eq!(pixels(&frame).collect::<Vec<_>>(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn subtract_stops_at_shorter_frame() {
+        let mut frame = packed(&[10, 20, 30, 40, 50, 60, 70, 80]);
+        let dark = packed(&[1, 2, 3, 4]);
+        subtract(&mut frame, &dark);
+        assert_eq!(
+            pixels(&frame).collect::<Vec<_>>(),
+            [9, 18, 27, 36, 50, 60, 70, 80]
+        );
+    }
+}
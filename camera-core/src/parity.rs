@@ -0,0 +1,267 @@
+//! Calibration of the analog offset and gain of the four row/column parity
+//! planes, which otherwise leave a 2x2 pattern in images.
+//!
+//! Offsets are solved from two dark frames taken with different offsets so
+//! that every plane ends up at the mean black level of the four. Gains are
+//! solved from an evenly lit frame so that every plane gives the mean
+//! response of the four. The global gain register overwrites the plane gain
+//! registers, so gains are kept as ratios to the global gain and turned into
+//! register values for every capture.
+
+use crate::{
+    exposure::Gain,
+    geometry::Area,
+    metering::WHITE_LEVEL,
+    packed::{self, GROUP_BYTES, GROUP_PIXELS},
+};
+
+pub const GAIN_SHIFT: u32 = 12;
+pub const UNITY: u16 = 1 << GAIN_SHIFT;
+
+/// Offset register values apart of the two dark frames offsets are solved
+/// from.
+pub const OFFSET_STEP: i16 = 32;
+const MAX_OFFSET: f32 = 255.0;
+/// Smallest change of the black level per offset step which is trusted.
+const MIN_OFFSET_SLOPE: f32 = 0.05;
+/// Flat frames have to be at least this far above black and below white.
+const MIN_SIGNAL: f32 = 64.0;
+
+/// Parity planes in the order of the arrays below.
+pub const EVEN_ROW_EVEN_COLUMN: usize = 0;
+pub const EVEN_ROW_ODD_COLUMN: usize = 1;
+pub const ODD_ROW_EVEN_COLUMN: usize = 2;
+pub const ODD_ROW_ODD_COLUMN: usize = 3;
+
+const fn plane(x: u16, y: u16) -> usize {
+    ((y & 1) * 2 + (x & 1)) as usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParityCalibration {
+    /// Analog offsets for `BlackLevelMode::Manual`.
+    pub offsets: [i16; 4],
+    /// Gains relative to the global gain, with `GAIN_SHIFT` fractional bits.
+    pub gains: [u16; 4],
+    /// The black level the offsets bring every plane to.
+    pub black_level: u16,
+}
+
+impl ParityCalibration {
+    pub const NONE: Self = Self {
+        offsets: [0; 4],
+        gains: [UNITY; 4],
+        black_level: 0,
+    };
+
+    /// The gain of `plane` when the global gain is `gain`.
+    pub fn plane_gain(&self, plane: usize, gain: Gain) -> Gain {
+        let ratio = self.gains[plane] as f32 / UNITY as f32;
+        Gain::new((gain.as_f32() * ratio).clamp(Gain::MIN, Gain::MAX)).unwrap_or(gain)
+    }
+}
+
+/// Returns the mean pixel value of every parity plane in `area` of a packed
+/// readout `frame`.
+pub fn plane_means(frame: &[u8], frame_width: u16, area: Area) -> [f32; 4] {
+    let mut sums = [0u32; 4];
+    let mut counts = [0u32; 4];
+    for y in area.top..area.bottom {
+        let first = y as usize * frame_width as usize + area.left as usize;
+        let start = first / GROUP_PIXELS * GROUP_BYTES;
+        let len = area.width() as usize / GROUP_PIXELS * GROUP_BYTES;
+        let Some(row) = frame.get(start..start + len) else {
+            break;
+        };
+        for (i, pixel) in packed::pixels(row).enumerate() {
+            let plane = plane(area.left + i as u16, y);
+            sums[plane] += pixel as u32;
+            counts[plane] += 1;
+        }
+    }
+
+    let mut means = [0.0; 4];
+    for ((mean, sum), count) in means.iter_mut().zip(sums).zip(counts) {
+        *mean = sum as f32 / count.max(1) as f32;
+    }
+    means
+}
+
+fn mean(values: &[f32; 4]) -> f32 {
+    values.iter().sum::<f32>() / 4.0
+}
+
+/// Solves the offsets from the plane means of dark frames taken with all
+/// offsets 0 and all offsets `OFFSET_STEP`. The calibration is left alone
+/// if they cannot be solved.
+pub fn solve_offsets(
+    calibration: &mut ParityCalibration,
+    zero: [f32; 4],
+    stepped: [f32; 4],
+) -> Result<(), ParityError> {
+    let target = mean(&zero);
+    let mut offsets = [0; 4];
+    for (plane, offset) in offsets.iter_mut().enumerate() {
+        let slope = (stepped[plane] - zero[plane]) / OFFSET_STEP as f32;
+        if slope.abs() < MIN_OFFSET_SLOPE {
+            return Err(ParityError::Offset);
+        }
+        let solved = (target - zero[plane]) / slope;
+        let solved = solved + if solved < 0.0 { -0.5 } else { 0.5 };
+        if solved.abs() > MAX_OFFSET + 0.5 {
+            return Err(ParityError::Offset);
+        }
+        *offset = solved as i16;
+    }
+    calibration.offsets = offsets;
+    calibration.black_level = (target + 0.5) as u16;
+    Ok(())
+}
+
+/// Solves the gains from the plane means of an evenly lit frame taken with
+/// all gains at unity. The calibration is left alone if they cannot be
+/// solved.
+pub fn solve_gains(calibration: &mut ParityCalibration, flat: [f32; 4]) -> Result<(), ParityError> {
+    let black_level = calibration.black_level as f32;
+    let mut signals = [0.0; 4];
+    for (signal, flat) in signals.iter_mut().zip(flat) {
+        *signal = flat - black_level;
+        if *signal < MIN_SIGNAL || flat > (WHITE_LEVEL as f32 - MIN_SIGNAL) {
+            return Err(ParityError::Signal);
+        }
+    }
+    let target = mean(&signals);
+    for (gain, signal) in calibration.gains.iter_mut().zip(signals) {
+        *gain = (target / signal * UNITY as f32 + 0.5).min(u16::MAX as f32) as u16;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ParityError {
+    /// The black level did not follow the offsets, or they cannot reach it.
+    Offset,
+    /// The flat frame was too dark or too bright.
+    Signal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn plane_means_average_every_plane() {
+        // An 8x4 frame whose even rows read 10 and 20 and odd rows 30 and
+        // 40, apart from a brighter column outside the area.
+        let pixels: Vec<u16> = (0..32)
+            .map(|i| match (i / 8 % 2, i % 8) {
+                (_, 7) => 1000,
+                (row, column) => 10 + row * 20 + column % 2 * 10,
+            })
+            .collect();
+        let frame: Vec<u8> = pixels
+            .chunks_exact(GROUP_PIXELS)
+            .flat_map(|group| packed::pack(group.try_into().unwrap()))
+            .collect();
+        let area = Area {
+            top: 0,
+            left: 0,
+            bottom: 4,
+            right: 4,
+        };
+        assert_eq!(plane_means(&frame, 8, area), [10.0, 20.0, 30.0, 40.0]);
+    }
+
+    #[test]
+    fn offsets_bring_every_plane_to_the_mean() {
+        let mut calibration = ParityCalibration::NONE;
+        // Every offset step raises the black level by half a step.
+        let zero = [40.0, 44.0, 36.0, 40.0];
+        let stepped = zero.map(|mean| mean + OFFSET_STEP as f32 / 2.0);
+        solve_offsets(&mut calibration, zero, stepped).unwrap();
+        assert_eq!(calibration.offsets, [0, -8, 8, 0]);
+        assert_eq!(calibration.black_level, 40);
+        assert_eq!(calibration.gains, ParityCalibration::NONE.gains);
+    }
+
+    #[test]
+    fn unsolvable_offsets_leave_the_calibration_alone() {
+        let calibration = ParityCalibration {
+            offsets: [1, 2, 3, 4],
+            gains: [UNITY; 4],
+            black_level: 42,
+        };
+        let zero = [40.0, 44.0, 36.0, 40.0];
+        // The last plane does not follow the offsets.
+        let mut stepped = zero.map(|mean| mean + 16.0);
+        stepped[3] = zero[3];
+        let mut solved = calibration;
+        assert!(matches!(
+            solve_offsets(&mut solved, zero, stepped),
+            Err(ParityError::Offset)
+        ));
+        assert_eq!(solved, calibration);
+
+        // The last plane is more offset steps away than the register holds.
+        let zero = [100.0, 100.0, 100.0, 400.0];
+        let stepped = zero.map(|mean| mean + 16.0);
+        assert!(matches!(
+            solve_offsets(&mut solved, zero, stepped),
+            Err(ParityError::Offset)
+        ));
+        assert_eq!(solved, calibration);
+    }
+
+    #[test]
+    fn gains_bring_every_plane_to_the_mean_signal() {
+        let mut calibration = ParityCalibration {
+            black_level: 40,
+            ..ParityCalibration::NONE
+        };
+        solve_gains(&mut calibration, [140.0, 240.0, 190.0, 190.0]).unwrap();
+        assert_eq!(
+            calibration.gains,
+            [UNITY * 3 / 2, UNITY * 3 / 4, UNITY, UNITY]
+        );
+        assert_eq!(calibration.black_level, 40);
+    }
+
+    #[test]
+    fn too_dark_or_bright_flat_frames_leave_the_calibration_alone() {
+        let calibration = ParityCalibration {
+            offsets: [1, 2, 3, 4],
+            gains: [UNITY + 1; 4],
+            black_level: 40,
+        };
+        for flat in [[140.0, 100.0, 190.0, 190.0], [140.0, 240.0, 990.0, 190.0]] {
+            let mut solved = calibration;
+            assert!(matches!(
+                solve_gains(&mut solved, flat),
+                Err(ParityError::Signal)
+            ));
+            assert_eq!(solved, calibration);
+        }
+    }
+
+    #[test]
+    fn plane_gains_round_and_clamp_to_the_register_range() {
+        let calibration = ParityCalibration {
+            gains: [UNITY / 2, UNITY * 2, UNITY + UNITY / 64, UNITY * 3 / 2],
+            ..ParityCalibration::NONE
+        };
+        let gain = |plane, gain| {
+            calibration
+                .plane_gain(plane, Gain::new(gain).unwrap())
+                .as_f32()
+        };
+        // 2.03 and 3.05 round to the nearest eighths, 2.0 and 3.0.
+        assert_eq!(gain(2, 2.0), 2.0);
+        assert_eq!(gain(2, 3.0), 3.0);
+        assert_eq!(gain(3, 1.25), 1.875);
+        assert_eq!(gain(3, 2.0), 3.0);
+        assert_eq!(gain(0, 1.0), Gain::MIN);
+        assert_eq!(gain(0, 4.0), 2.0);
+        assert_eq!(gain(1, 12.0), Gain::MAX);
+    }
+}
@@ -0,0 +1,786 @@
+//! The capture and save pipeline behind the camera state machine.
+
+use embedded_hal::{digital::OutputPin, i2c::I2c};
+
+use mt9m001::AnalogOffset;
+
+use crate::{
+    buffer,
+    camera::{CaptureBackend, CaptureKind},
+    controls::{Controls, DialInputs, ExposureMode, ExposureSettings, GAINS},
+    counter::FrameCounter,
+    exposure::{Exposure, Gain},
+    flatfield::{self, Accumulator, FlatField, HEADER_WORDS},
+    geometry,
+    metering::{self, AutoExposure, MeteringMode, Region},
+    packed,
+    parity::{self, ParityCalibration},
+    platform::{ClockControl, FrameSource, PersistentMemory, Storage, Timer},
+    sensor,
+    sensor::{BlackLevelMode, CaptureInfo, Sensor},
+    settings::{CameraSettings, SettingsStore},
+    tiff::{
+        Dng, FileFormat, Image, Metadata, Registers, write_monochrome_dng,
+        write_single_directory_monochrome_tiff,
+    },
+};
+
+const PREVIEW_WORDS: usize =
+    sensor::PREVIEW_WIDTH as usize * sensor::PREVIEW_HEIGHT as usize * 10 / 32;
+const _: () = assert!(
+    (sensor::PREVIEW_WIDTH as usize * sensor::PREVIEW_HEIGHT as usize * 10).is_multiple_of(32)
+);
+
+/// Previews taken at most before the auto exposure gives up converging and
+/// uses its latest solution.
+const AUTO_EXPOSURE_ITERATIONS: usize = 4;
+
+/// Evenly lit frames averaged by a flat-field calibration.
+const FLAT_FIELD_FRAMES: usize = 8;
+/// Maps calibrated on the camera have a gain per tile of the active area;
+/// per-pixel maps come from the host tool.
+const FLAT_FIELD_HEADER: flatfield::Header = flatfield::Header {
+    area: geometry::ACTIVE,
+    tile: 32,
+};
+const FLAT_FIELD_TILES: usize = FLAT_FIELD_HEADER.tiles();
+
+/// What the frame being captured is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Purpose {
+    Image,
+    DarkFrame,
+    FlatField,
+}
+
+/// Binds the camera state machine to the board: the sensor, the frame
+/// source, the exposure dials, the persistent memory holding the settings
+/// and the image counter, and the storage images are saved to.
+pub struct Pipeline<'a, I2C, SP, TP, C, T, F, M, S, LED, D>
+where
+    I2C: I2c,
+    SP: OutputPin,
+    TP: OutputPin,
+    C: ClockControl,
+    T: Timer,
+    F: FrameSource,
+    M: PersistentMemory,
+    S: Storage,
+    LED: OutputPin,
+    D: DialInputs,
+{
+    pub sensor: Sensor<I2C, SP, TP, C, T>,
+    /// Asleep except while it is accessed.
+    pub memory: M,
+    pub storage: S,
+    pub status_led: LED,
+    dials: D,
+    controls: Controls,
+    settings: ExposureSettings,
+    frame_source: F,
+    purpose: Purpose,
+    last_capture: Option<CaptureInfo>,
+    dark_frame: &'a mut [u32],
+    /// Settings the dark frame was taken with, if there is one.
+    dark_settings: Option<ExposureSettings>,
+    flat_field_accumulator: Accumulator<FLAT_FIELD_TILES>,
+    /// The flat-field map in use in its stored format.
+    flat_field: &'a mut [u16],
+    /// Words of `flat_field` in use, 0 if there is no map.
+    flat_field_len: usize,
+    camera_settings: CameraSettings,
+    settings_store: SettingsStore,
+    frame_counter: FrameCounter,
+}
+
+impl<'a, I2C, SP, TP, C, T, F, M, S, LED, D> Pipeline<'a, I2C, SP, TP, C, T, F, M, S, LED, D>
+where
+    I2C: I2c,
+    SP: OutputPin,
+    TP: OutputPin,
+    C: ClockControl,
+    T: Timer,
+    F: FrameSource,
+    M: PersistentMemory,
+    S: Storage,
+    LED: OutputPin,
+    D: DialInputs,
+{
+    /// Panics if `dark_frame` cannot hold a frame of `frame_source`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sensor: Sensor<I2C, SP, TP, C, T>,
+        memory: M,
+        storage: S,
+        status_led: LED,
+        dials: D,
+        frame_source: F,
+        dark_frame: &'a mut [u32],
+        flat_field: &'a mut [u16],
+        camera_settings: CameraSettings,
+        settings_store: SettingsStore,
+        frame_counter: FrameCounter,
+    ) -> Self {
+        assert!(
+            dark_frame.len() >= frame_source.frame().len(),
+            "the dark frame buffer is smaller than a frame"
+        );
+        Self {
+            sensor,
+            memory,
+            storage,
+            status_led,
+            dials,
+            controls: Controls::new(),
+            settings: camera_settings.exposure,
+            frame_source,
+            purpose: Purpose::Image,
+            last_capture: None,
+            dark_frame,
+            dark_settings: None,
+            flat_field_accumulator: Accumulator::new(FLAT_FIELD_HEADER),
+            flat_field,
+            flat_field_len: 0,
+            camera_settings,
+            settings_store,
+            frame_counter,
+        }
+    }
+
+    /// Loads the flat-field map from the storage, if there is one.
+    pub fn load_flat_field(&mut self) -> Result<(), PipelineError> {
+        let bytes = buffer::as_bytes_mut(self.flat_field);
+        let len = self
+            .storage
+            .read_file(flatfield::FILE_NAME, bytes)
+            .map_err(|_| PipelineError::FlatField)?;
+        let Some(len) = len else {
+            return Ok(());
+        };
+        FlatField::parse(&self.flat_field[..len / 2], geometry::READOUT.width())
+            .map_err(|_| PipelineError::FlatField)?;
+        self.flat_field_len = len / 2;
+        Ok(())
+    }
+
+    /// Reads a frame into the first `words` words of the frame buffer.
+    fn grab(
+        &mut self,
+        words: usize,
+        settings: ExposureSettings,
+    ) -> Result<CaptureInfo, PipelineError> {
+        self.frame_source
+            .start(words)
+            .map_err(|_| PipelineError::Capture)?;
+
+        let ExposureSettings { exposure, gain } = settings;
+        let frame_source = &mut self.frame_source;
+        let (received, info) = self
+            .sensor
+            .configure_and_capture(gain, exposure, || frame_source.wait())
+            .map_err(|_| PipelineError::Capture)?;
+        received.map_err(|_| PipelineError::Capture)?;
+        Ok(info)
+    }
+
+    /// Returns the parity plane means of the frame in the frame buffer.
+    fn plane_means(&self) -> [f32; 4] {
+        let frame = buffer::as_bytes(self.frame_source.frame());
+        parity::plane_means(frame, geometry::READOUT.width(), geometry::ACTIVE)
+    }
+
+    /// Solves the parity plane offsets from two dark frames taken with
+    /// manual offsets.
+    fn solve_offsets(&mut self, words: usize) -> Result<ParityCalibration, PipelineError> {
+        let mut parity = self.sensor.parity();
+        let mut means = [[0.0; 4]; 2];
+        for (offset, means) in [0, parity::OFFSET_STEP].into_iter().zip(&mut means) {
+            self.sensor
+                .set_black_level(BlackLevelMode::Manual([offset; 4]));
+            self.sensor
+                .apply_black_level()
+                .map_err(|_| PipelineError::Capture)?;
+            self.grab(words, self.settings)?;
+            *means = self.plane_means();
+        }
+        parity::solve_offsets(&mut parity, means[0], means[1])
+            .map_err(|_| PipelineError::Calibration)?;
+        Ok(parity)
+    }
+
+    /// Solves the parity plane offsets, then exposes the dark frame in the
+    /// black level mode the sensor was in. A manual black level takes the
+    /// new offsets.
+    fn grab_dark_frame(&mut self, words: usize) -> Result<CaptureInfo, PipelineError> {
+        let mode = self.sensor.black_level();
+        let solved = self.solve_offsets(words);
+        let mode = match (mode, &solved) {
+            (BlackLevelMode::Manual(_), Ok(parity)) => BlackLevelMode::Manual(parity.offsets),
+            (mode, _) => mode,
+        };
+        if let Ok(parity) = solved {
+            self.sensor.set_parity(parity);
+        }
+        self.sensor.set_black_level(mode);
+        self.sensor
+            .apply_black_level()
+            .map_err(|_| PipelineError::Capture)?;
+        solved?;
+        self.grab(words, self.settings)
+    }
+
+    /// Keeps the parity plane calibration and black level mode in use in
+    /// the settings.
+    fn store_parity(&mut self) -> Result<(), PipelineError> {
+        self.camera_settings.calibration = self.sensor.parity();
+        self.camera_settings.black_level = self.sensor.black_level();
+        self.with_memory(PipelineError::Calibration, |pipeline| {
+            pipeline
+                .settings_store
+                .store(&mut pipeline.memory, &pipeline.camera_settings)
+                .map_err(|_| PipelineError::Calibration)
+        })
+    }
+
+    /// Wakes the persistent memory up for `f` and puts it back to sleep
+    /// afterwards.
+    fn with_memory<R>(
+        &mut self,
+        error: PipelineError,
+        f: impl FnOnce(&mut Self) -> Result<R, PipelineError>,
+    ) -> Result<R, PipelineError> {
+        self.memory.wake().map_err(|_| error)?;
+        let result = f(self);
+        self.memory.sleep().map_err(|_| error)?;
+        result
+    }
+
+    /// Solves the parity plane gains from an evenly lit frame, then exposes
+    /// evenly lit frames with them and adds them to the flat-field
+    /// accumulator.
+    fn grab_flat_frames(&mut self, words: usize) -> Result<CaptureInfo, PipelineError> {
+        let mut parity = self.sensor.parity();
+        self.sensor.set_parity(ParityCalibration {
+            gains: ParityCalibration::NONE.gains,
+            ..parity
+        });
+        let solved = self.grab(words, self.settings).and_then(|_| {
+            parity::solve_gains(&mut parity, self.plane_means())
+                .map_err(|_| PipelineError::Calibration)
+        });
+        // Unchanged if the gains could not be solved.
+        self.sensor.set_parity(parity);
+        solved?;
+
+        self.flat_field_accumulator.clear();
+        let mut capture = None;
+        for _ in 0..FLAT_FIELD_FRAMES {
+            capture = Some(self.grab(words, self.settings)?);
+            let dark_subtracted = self.subtract_dark_frame();
+            let black_level = self.black_level(dark_subtracted);
+            let frame = buffer::as_bytes(self.frame_source.frame());
+            self.flat_field_accumulator
+                .add(frame, geometry::READOUT.width(), black_level);
+        }
+        capture.ok_or(PipelineError::Capture)
+    }
+
+    /// The black level of the image in the frame buffer. Subtracting the dark
+    /// frame takes it off, and the manual offsets of the parity calibration
+    /// bring every plane to the calibrated one. Otherwise it is the one
+    /// configured for DNG files, or 0 for TIFF files.
+    fn black_level(&self, dark_subtracted: bool) -> u16 {
+        let configured = match self.camera_settings.file_format {
+            FileFormat::Dng(dng) => dng.black_level,
+            FileFormat::Tiff => 0,
+        };
+        let parity = self.sensor.parity();
+        match self.sensor.black_level() {
+            _ if dark_subtracted => 0,
+            // The calibrated black level stays 0 until offsets are solved.
+            BlackLevelMode::Manual(offsets)
+                if offsets == parity.offsets && parity.black_level != 0 =>
+            {
+                parity.black_level
+            }
+            _ => configured,
+        }
+    }
+
+    /// Subtracts the dark frame from the frame in the frame buffer if it was
+    /// exposed the same way, as a dark frame only matches those. Returns
+    /// whether it did.
+    fn subtract_dark_frame(&mut self) -> bool {
+        if self.dark_settings != Some(self.settings) {
+            return false;
+        }
+        let frame = buffer::as_bytes_mut(self.frame_source.frame_mut());
+        let dark = buffer::as_bytes(self.dark_frame);
+        packed::subtract(frame, dark);
+        true
+    }
+
+    /// Derives a map from the accumulated flat frames, stores it and uses it
+    /// for the following images.
+    fn save_flat_field(&mut self) -> Result<(), PipelineError> {
+        let header = self.flat_field_accumulator.header();
+        let len = HEADER_WORDS + header.tiles();
+        self.flat_field[..HEADER_WORDS].copy_from_slice(&header.to_words());
+        self.flat_field_accumulator
+            .gains(&mut self.flat_field[HEADER_WORDS..len]);
+        self.flat_field_len = len;
+
+        let bytes = buffer::as_bytes(&self.flat_field[..len]);
+        self.storage
+            .write_file(flatfield::FILE_NAME, bytes)
+            .map_err(|_| PipelineError::Save)
+    }
+
+    fn auto_expose(&mut self, mode: MeteringMode) -> Result<ExposureSettings, PipelineError> {
+        let region_percent = if mode == MeteringMode::Spot { 10 } else { 50 };
+        let auto_exposure = AutoExposure {
+            mode,
+            region: Region::centered(
+                sensor::PREVIEW_WIDTH,
+                sensor::PREVIEW_HEIGHT,
+                region_percent,
+            ),
+            target: 184, // 18% grey
+            highlight_permille: 990,
+            max_handheld_us: 1_000_000 / 30,
+            min_exposure_us: 100,
+            max_exposure_us: 1_000_000,
+            max_gain: GAINS[GAINS.len() - 1].as_f32(),
+        };
+
+        self.sensor.set_preview(true);
+        let result = self.meter_previews(&auto_exposure);
+        self.sensor.set_preview(false);
+        result
+    }
+
+    fn meter_previews(
+        &mut self,
+        auto_exposure: &AutoExposure,
+    ) -> Result<ExposureSettings, PipelineError> {
+        let mut settings = self.camera_settings.exposure;
+        for _ in 0..AUTO_EXPOSURE_ITERATIONS {
+            let info = self.grab(PREVIEW_WORDS, settings)?;
+
+            let frame = self.frame_source.frame();
+            let preview = buffer::as_bytes(&frame[..PREVIEW_WORDS]);
+            let measurement = metering::measure(
+                preview,
+                sensor::PREVIEW_WIDTH,
+                sensor::PREVIEW_HEIGHT,
+                auto_exposure.mode,
+                auto_exposure.region,
+            );
+            let solution =
+                auto_exposure.solve(&measurement, info.integration_us, info.gain.as_f32());
+            settings = ExposureSettings {
+                exposure: Exposure::from_micros(solution.exposure_us)
+                    .map_err(|_| PipelineError::Capture)?,
+                gain: Gain::new(solution.gain).map_err(|_| PipelineError::Capture)?,
+            };
+            if solution.converged {
+                break;
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+impl<I2C, SP, TP, C, T, F, M, S, LED, D> CaptureBackend
+    for Pipeline<'_, I2C, SP, TP, C, T, F, M, S, LED, D>
+where
+    I2C: I2c,
+    SP: OutputPin,
+    TP: OutputPin,
+    C: ClockControl,
+    T: Timer,
+    F: FrameSource,
+    M: PersistentMemory,
+    S: Storage,
+    LED: OutputPin,
+    D: DialInputs,
+{
+    type Error = PipelineError;
+
+    fn arm(&mut self, kind: CaptureKind) -> Result<(), PipelineError> {
+        let dials = self.dials.sample().map_err(|_| PipelineError::Controls)?;
+        let _ = self.status_led.set_high();
+        let mode = self.controls.update(dials);
+        self.purpose = match (kind, mode) {
+            (CaptureKind::Image, _) => Purpose::Image,
+            (CaptureKind::Calibration, ExposureMode::Manual(_)) => Purpose::DarkFrame,
+            (CaptureKind::Calibration, ExposureMode::Auto(_)) => Purpose::FlatField,
+        };
+        let settings = match mode {
+            ExposureMode::Manual(settings) => Ok(settings),
+            ExposureMode::Auto(mode) => self.auto_expose(mode),
+        };
+        // Nothing is exposed after a failure.
+        if settings.is_err() {
+            let _ = self.status_led.set_low();
+        }
+        self.settings = settings?;
+        Ok(())
+    }
+
+    fn expose(&mut self) -> Result<(), PipelineError> {
+        let words = self.frame_source.frame().len();
+        let result = match self.purpose {
+            Purpose::Image => self.grab(words, self.settings),
+            Purpose::DarkFrame => self.grab_dark_frame(words),
+            Purpose::FlatField => self.grab_flat_frames(words),
+        };
+        let _ = self.status_led.set_low();
+        self.last_capture = Some(result?);
+        Ok(())
+    }
+
+    fn save(&mut self) -> Result<(), PipelineError> {
+        let capture = self.last_capture.take().ok_or(PipelineError::Save)?;
+
+        match self.purpose {
+            Purpose::Image => {}
+            Purpose::DarkFrame => {
+                let buffer = self.frame_source.frame();
+                self.dark_frame[..buffer.len()].copy_from_slice(buffer);
+                self.dark_settings = Some(self.settings);
+                return self.store_parity();
+            }
+            Purpose::FlatField => {
+                self.store_parity()?;
+                return self.save_flat_field();
+            }
+        }
+
+        let dark_subtracted = self.subtract_dark_frame();
+        let black_level = self.black_level(dark_subtracted);
+        let frame = buffer::as_bytes_mut(self.frame_source.frame_mut());
+        if self.flat_field_len > 0 {
+            FlatField::parse(
+                &self.flat_field[..self.flat_field_len],
+                geometry::READOUT.width(),
+            )
+            .map_err(|_| PipelineError::FlatField)?
+            .apply(frame, black_level);
+        }
+
+        // The name is checked before its number is used up.
+        let file_name = self
+            .camera_settings
+            .file_naming
+            .file_name(self.frame_counter.next(), &self.camera_settings.file_format)
+            .ok_or(PipelineError::FrameCounter)?;
+        let image_number = self.with_memory(PipelineError::FrameCounter, |pipeline| {
+            pipeline
+                .frame_counter
+                .take(&mut pipeline.memory)
+                .map_err(|_| PipelineError::FrameCounter)
+        })?;
+        let metadata = Metadata {
+            image_number: image_number as u32,
+            exposure_us: capture.integration_us,
+            iso: capture.gain.iso(),
+            date_time: self.storage.now(),
+            registers: Registers {
+                shutter_width: capture.shutter_width,
+                global_gain: capture.gain.register(),
+                column_start: capture.window.column_start,
+                row_start: capture.window.row_start,
+                column_size: capture.window.column_size,
+                row_size: capture.window.row_size,
+                analog_offsets: capture.black_level_offsets.map(AnalogOffset::register),
+            },
+            row_time: capture.row_time,
+            frame: capture.frame,
+            timestamp_us: capture.timestamp_us,
+        };
+        let frame = buffer::as_bytes(self.frame_source.frame());
+        let image = Image {
+            frame,
+            frame_width: geometry::READOUT.width(),
+            area: self.camera_settings.framing.saved_area(),
+            active_area: geometry::ACTIVE,
+        };
+        let layout = self.camera_settings.sample_layout;
+        let file_format = match self.camera_settings.file_format {
+            FileFormat::Dng(dng) => FileFormat::Dng(Dng { black_level, ..dng }),
+            FileFormat::Tiff => FileFormat::Tiff,
+        };
+        let file_name = str::from_utf8(file_name.as_bytes()).map_err(|_| PipelineError::Save)?;
+        self.storage
+            .create_file(file_name, |write_all| match &file_format {
+                FileFormat::Tiff => {
+                    write_single_directory_monochrome_tiff(write_all, &image, layout, &metadata)
+                }
+                FileFormat::Dng(dng) => {
+                    write_monochrome_dng(write_all, &image, layout, &metadata, dng)
+                }
+            })
+            .map_err(|_| PipelineError::Save)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PipelineError {
+    Controls,
+    Capture,
+    FrameCounter,
+    Save,
+    FlatField,
+    Calibration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        camera::CaptureBackend,
+        controls::{ADC_FULL_SCALE, DialPositions},
+        naming::FileNaming,
+        sim::{
+            SimClock, SimDials, SimFrameSource, SimI2c, SimMemory, SimPin, SimStorage, SimTimer,
+            memory,
+        },
+        tiff::SampleLayout,
+    };
+    use embedded_hal::digital::InputPin;
+    use std::{vec, vec::Vec};
+
+    const WORDS: usize =
+        geometry::READOUT.width() as usize * geometry::READOUT.height() as usize * 10 / 32;
+    const BLACK_LEVEL: u16 = 40;
+    /// The fastest shutter speed at unity gain.
+    const MANUAL: DialPositions = DialPositions {
+        shutter_speed: 0,
+        gain: 0,
+    };
+    /// Auto exposure with average metering.
+    const AUTO: DialPositions = DialPositions {
+        shutter_speed: ADC_FULL_SCALE,
+        gain: 0,
+    };
+
+    const TAG_IMAGE_WIDTH: u16 = 256;
+    const TAG_IMAGE_HEIGHT: u16 = 257;
+    const TAG_BITS_PER_SAMPLE: u16 = 258;
+    const TAG_STRIP_OFFSETS: u16 = 273;
+    const TAG_STRIP_BYTE_COUNT: u16 = 279;
+    const TAG_DNG_VERSION: u16 = 50706;
+    const TAG_BLACK_LEVEL: u16 = 50714;
+
+    type SimPipeline<'a> = Pipeline<
+        'a,
+        SimI2c,
+        SimPin,
+        SimPin,
+        SimClock,
+        SimTimer,
+        SimFrameSource,
+        SimMemory,
+        SimStorage,
+        SimPin,
+        SimDials,
+    >;
+
+    fn defaults() -> CameraSettings {
+        CameraSettings {
+            exposure: ExposureSettings {
+                exposure: Exposure::from_micros(10_000).unwrap(),
+                gain: Gain::UNITY,
+            },
+            sample_layout: SampleLayout::Packed10,
+            file_format: FileFormat::Dng(Dng {
+                black_level: 0,
+                linearization_table: None,
+            }),
+            framing: geometry::Framing::Crop,
+            file_naming: FileNaming::new(*b"IM").unwrap(),
+            calibration: ParityCalibration::NONE,
+            black_level: BlackLevelMode::Manual([0; 4]),
+        }
+    }
+
+    /// A camera on the simulated board, put together like the firmware does
+    /// it, whose sensor sees no light.
+    fn pipeline<'a>(dark_frame: &'a mut [u32], flat_field: &'a mut [u16]) -> SimPipeline<'a> {
+        let timer = SimTimer::default();
+        let i2c = SimI2c::new();
+        let mut memory = memory(timer.clone());
+        memory.protect().unwrap();
+        let (settings_store, camera_settings) =
+            SettingsStore::load(&mut memory, defaults()).unwrap();
+        let frame_counter = FrameCounter::load(&mut memory).unwrap();
+        memory.sleep().unwrap();
+
+        let mut sensor = Sensor::new(
+            SimClock::default(),
+            timer,
+            i2c.clone(),
+            SimPin::default(),
+            SimPin::default(),
+        );
+        sensor.set_parity(camera_settings.calibration);
+        sensor.set_black_level(camera_settings.black_level);
+        sensor.init().unwrap();
+
+        let mut frame_source = SimFrameSource::new(i2c, WORDS);
+        frame_source.black_level = BLACK_LEVEL;
+        let mut pipeline = Pipeline::new(
+            sensor,
+            memory,
+            SimStorage::new(None),
+            SimPin::default(),
+            SimDials::new(MANUAL),
+            frame_source,
+            dark_frame,
+            flat_field,
+            camera_settings,
+            settings_store,
+            frame_counter,
+        );
+        pipeline.load_flat_field().unwrap();
+        pipeline
+    }
+
+    fn capture(pipeline: &mut SimPipeline, kind: CaptureKind) {
+        pipeline.arm(kind).unwrap();
+        pipeline.expose().unwrap();
+        pipeline.save().unwrap();
+    }
+
+    fn u16_at(file: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([file[offset], file[offset + 1]])
+    }
+
+    fn u32_at(file: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Returns the value or offset of the entry `tag` of the first IFD.
+    fn entry(file: &[u8], tag: u16) -> Option<u32> {
+        let ifd = u32_at(file, 4) as usize;
+        (0..u16_at(file, ifd) as usize)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|&entry| u16_at(file, entry) == tag)
+            .map(|entry| match u16_at(file, entry + 2) {
+                3 => u16_at(file, entry + 8) as u32,
+                _ => u32_at(file, entry + 8),
+            })
+    }
+
+    /// Checks that `file` is a DNG of the active area and returns its black
+    /// level and pixels.
+    fn read_dng(file: &[u8]) -> (u32, Vec<u16>) {
+        assert_eq!(file[..4], *b"II*\0");
+        assert_eq!(
+            entry(file, TAG_DNG_VERSION),
+            Some(u32::from_le_bytes([1, 4, 0, 0]))
+        );
+        let width = entry(file, TAG_IMAGE_WIDTH).unwrap();
+        let height = entry(file, TAG_IMAGE_HEIGHT).unwrap();
+        assert_eq!(width, geometry::ACTIVE.width() as u32);
+        assert_eq!(height, geometry::ACTIVE.height() as u32);
+        assert_eq!(entry(file, TAG_BITS_PER_SAMPLE), Some(10));
+        let offset = entry(file, TAG_STRIP_OFFSETS).unwrap() as usize;
+        let len = entry(file, TAG_STRIP_BYTE_COUNT).unwrap() as usize;
+        assert_eq!(len, (width * height * 10 / 8) as usize);
+        let pixels = packed::pixels(&file[offset..offset + len]).collect();
+        (entry(file, TAG_BLACK_LEVEL).unwrap(), pixels)
+    }
+
+    #[test]
+    fn image_is_saved_as_dng() {
+        let (mut dark_frame, mut flat_field) = (vec![0; WORDS], vec![0; 1]);
+        let mut pipeline = pipeline(&mut dark_frame, &mut flat_field);
+        capture(&mut pipeline, CaptureKind::Image);
+        capture(&mut pipeline, CaptureKind::Image);
+
+        let files: Vec<_> = pipeline.storage.files.keys().collect();
+        assert_eq!(files, ["IM00000.DNG", "IM00001.DNG"]);
+        let (black_level, pixels) = read_dng(&pipeline.storage.files["IM00001.DNG"]);
+        assert_eq!(black_level, 0);
+        assert!(pixels.iter().all(|&pixel| pixel == BLACK_LEVEL));
+        assert!(pipeline.status_led.is_low().unwrap());
+    }
+
+    #[test]
+    fn dark_frame_calibrates_and_is_subtracted() {
+        let (mut dark_frame, mut flat_field) = (vec![0; WORDS], vec![0; 1]);
+        let mut pipeline = pipeline(&mut dark_frame, &mut flat_field);
+        capture(&mut pipeline, CaptureKind::Calibration);
+        assert!(pipeline.storage.files.is_empty());
+        let parity = pipeline.sensor.parity();
+        assert_ne!(parity.black_level, 0);
+
+        capture(&mut pipeline, CaptureKind::Image);
+        let (black_level, pixels) = read_dng(&pipeline.storage.files["IM00000.DNG"]);
+        assert_eq!(black_level, 0);
+        assert!(pixels.iter().all(|&pixel| pixel == 0));
+
+        // The calibration is kept in the F-RAM.
+        pipeline.memory.wake().unwrap();
+        let (_, stored) = SettingsStore::load(&mut pipeline.memory, defaults()).unwrap();
+        assert_eq!(stored.calibration, parity);
+        assert_eq!(stored.black_level, BlackLevelMode::Manual(parity.offsets));
+    }
+
+    #[test]
+    fn flat_field_calibrates_and_is_applied() {
+        let (mut dark_frame, mut flat_field) =
+            (vec![0; WORDS], vec![0; HEADER_WORDS + FLAT_FIELD_TILES]);
+        let mut pipeline = pipeline(&mut dark_frame, &mut flat_field);
+        // The offsets, and with them the black level, are calibrated first.
+        capture(&mut pipeline, CaptureKind::Calibration);
+        let black_level = pipeline.sensor.parity().black_level;
+        assert_eq!(black_level, BLACK_LEVEL);
+
+        pipeline.frame_source.light = 0.02;
+        pipeline.frame_source.plane_response = [1.0, 1.2, 0.9, 1.1];
+        pipeline.frame_source.vignetting = 0.3;
+        pipeline.dials.turn(AUTO);
+        capture(&mut pipeline, CaptureKind::Calibration);
+        assert!(pipeline.storage.files.contains_key(flatfield::FILE_NAME));
+        let parity = pipeline.sensor.parity();
+        assert!(parity.gains[1] < parity.gains[0] && parity.gains[0] < parity.gains[2]);
+
+        // The map is loaded back from the storage and applied to images
+        // which still hold the black level, as no dark frame matches them.
+        pipeline.flat_field_len = 0;
+        pipeline.load_flat_field().unwrap();
+        assert_eq!(pipeline.flat_field_len, HEADER_WORDS + FLAT_FIELD_TILES);
+        let gains = &pipeline.flat_field[HEADER_WORDS..];
+        let columns = FLAT_FIELD_HEADER.columns();
+        let center = (FLAT_FIELD_HEADER.rows() / 2) * columns + columns / 2;
+        let (corner, center) = (gains[0], gains[center]);
+        assert!(corner > flatfield::UNITY && center < flatfield::UNITY);
+
+        capture(&mut pipeline, CaptureKind::Image);
+        let (stored_black_level, pixels) = read_dng(&pipeline.storage.files["IM00000.DNG"]);
+        assert_eq!(stored_black_level, black_level as u32);
+        // Plane gains below unity cannot be programmed at unity global
+        // gain, so only every parity plane on its own comes out flat.
+        let width = geometry::ACTIVE.width() as usize;
+        for plane in 0..4 {
+            let signals: Vec<f32> = pixels
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| (i / width % 2) * 2 + i % 2 == plane)
+                .map(|(_, &pixel)| pixel as f32 - black_level as f32)
+                .collect();
+            let mean = signals.iter().sum::<f32>() / signals.len() as f32;
+            assert!(mean > 64.0);
+            assert!(
+                signals
+                    .iter()
+                    .all(|signal| (signal - mean).abs() < mean * 0.03),
+                "plane {plane} is not flat around {mean}"
+            );
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! What the camera needs from the board it runs on.
+
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+use fm25l16b::{BlockProtection, FM25L16B, FM25L16BError};
+
+use crate::tiff::DateTime;
+
+/// Starts and stops the clock the sensor runs on.
+pub trait ClockControl {
+    fn enable(&mut self);
+    fn disable(&mut self);
+}
+
+/// A delay which also tells the time since power-up.
+pub trait Timer: DelayNs {
+    fn now_us(&mut self) -> u64;
+}
+
+/// Receives frames from the sensor into the frame buffer.
+pub trait FrameSource {
+    type Error;
+
+    /// Starts receiving a frame of `words` words into the start of the frame
+    /// buffer. The sensor is triggered afterwards.
+    fn start(&mut self, words: usize) -> Result<(), Self::Error>;
+
+    /// Waits until the frame started last is received.
+    fn wait(&mut self) -> Result<(), Self::Error>;
+
+    /// The frame buffer, empty while a frame is being received.
+    fn frame(&self) -> &[u32];
+
+    fn frame_mut(&mut self) -> &mut [u32];
+}
+
+/// Where images and calibration files are saved.
+pub trait Storage {
+    type Error;
+
+    /// Returns the time files are stamped with, or `None` if the board has
+    /// no clock which knows it.
+    fn now(&self) -> Option<DateTime>;
+
+    /// Creates the file `file_name` and has `write` fill it through the
+    /// function it is passed, which appends bytes to the file. Fails if the
+    /// file exists. The name may start with a directory, like `DIR/NAME`,
+    /// which is created if it does not exist.
+    fn create_file<F>(&mut self, file_name: &str, write: F) -> Result<(), Self::Error>
+    where
+        F: FnOnce(&mut dyn FnMut(&[u8]) -> Result<(), Self::Error>) -> Result<(), Self::Error>;
+
+    /// Writes `bytes` into the file `file_name`, replacing it if it exists.
+    fn write_file(&mut self, file_name: &str, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Reads the file `file_name` into `buffer` and returns its length, or
+    /// `None` if there is no such file. Files longer than `buffer` are
+    /// truncated.
+    fn read_file(
+        &mut self,
+        file_name: &str,
+        buffer: &mut [u8],
+    ) -> Result<Option<usize>, Self::Error>;
+}
+
+/// Memory which keeps the settings and the image counter without power.
+pub trait PersistentMemory {
+    type Error;
+
+    fn read(&mut self, address: u16, bytes: &mut [u8]) -> Result<(), Self::Error>;
+
+    fn write(&mut self, address: u16, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Makes the memory accessible after `sleep`.
+    fn wake(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Saves power until `wake`.
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The part of the F-RAM the settings and the image counter are kept in.
+/// `Fram` write protects it except while writing.
+pub const RECORD_PROTECTION: BlockProtection = BlockProtection::UpperQuarter;
+
+/// An FM25L16B F-RAM and the delay it needs to wake up.
+pub struct Fram<SPI: SpiDevice, D: DelayNs> {
+    fm25l16b: FM25L16B<SPI>,
+    delay: D,
+}
+
+impl<SPI: SpiDevice, D: DelayNs> Fram<SPI, D> {
+    pub fn new(fm25l16b: FM25L16B<SPI>, delay: D) -> Self {
+        Self { fm25l16b, delay }
+    }
+
+    /// Write protects the records, so that only `write` changes them. Call
+    /// it at power-up; the protection is kept without power.
+    pub fn protect(&mut self) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.fm25l16b.set_block_protection(RECORD_PROTECTION)
+    }
+}
+
+impl<SPI: SpiDevice, D: DelayNs> PersistentMemory for Fram<SPI, D> {
+    type Error = FM25L16BError<SPI::Error>;
+
+    fn read(&mut self, address: u16, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.fm25l16b.read_bytes(address, bytes)
+    }
+
+    /// Lifts the protection of the records for the write.
+    fn write(&mut self, address: u16, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.fm25l16b.set_block_protection(BlockProtection::None)?;
+        let written = self.fm25l16b.write_bytes(address, bytes);
+        let protected = self.fm25l16b.set_block_protection(RECORD_PROTECTION);
+        written.and(protected)
+    }
+
+    fn wake(&mut self) -> Result<(), Self::Error> {
+        self.fm25l16b.wake(&mut self.delay)
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.fm25l16b.sleep()
+    }
+}
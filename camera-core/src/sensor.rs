@@ -0,0 +1,470 @@
+use embedded_hal::{digital::OutputPin, i2c::I2c};
+
+use mt9m001::{
+    AnalogOffset, ColumnSize, ColumnStart, HorizontalBlanking, InvalidValue, MT9M001, RowSize,
+    RowStart, ShutterWidth, VerticalBlanking,
+};
+
+use crate::{
+    exposure::{Exposure, Gain, OutOfRange, RowTiming},
+    geometry,
+    parity::{self, ParityCalibration},
+    platform::{ClockControl, Timer},
+};
+
+pub const HEIGHT: u16 = 1048;
+pub const WIDTH: u16 = 1310;
+pub const FREQUENCY: u32 = 6_500_000;
+
+/// Preview frames are read out with row and column skip 8, which keeps 2 of
+/// every 16 columns and rows.
+pub const PREVIEW_WIDTH: u16 = (WIDTH / 16) * 2 + 2;
+/// A preview frame of `PREVIEW_WIDTH` x `PREVIEW_HEIGHT` 10-bit pixels fills
+/// exactly 6765 32-bit words, so the last word is not padded.
+pub const PREVIEW_HEIGHT: u16 = (HEIGHT / 16) * 2 + 2;
+
+/// The part of the pixel array read out, as programmed into the window
+/// registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub column_start: u16,
+    pub row_start: u16,
+    pub column_size: u16,
+    pub row_size: u16,
+}
+
+/// What the sensor actually used for a capture. Integration time and gain
+/// differ from the requested ones because the registers quantize them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureInfo {
+    /// Achieved integration time in microseconds.
+    pub integration_us: u32,
+    /// Row time in pixel clock periods.
+    pub row_time: u32,
+    pub shutter_width: u16,
+    /// Programmed gain; `gain.as_f32()` is the effective analog gain.
+    pub gain: Gain,
+    pub window: Window,
+    /// Captures since power-up.
+    pub frame: u32,
+    /// Timer ticks in microseconds when the exposure was triggered.
+    pub timestamp_us: u64,
+    /// Analog offsets after the readout, in the order of the `parity`
+    /// planes.
+    pub black_level_offsets: [AnalogOffset; 4],
+}
+
+/// Lowest and highest black level the automatic correction keeps to, in
+/// ADC steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    register: mt9m001::CalThreshold,
+}
+
+impl Thresholds {
+    pub const fn new(low: u8, high: u8) -> Result<Self, OutOfRange> {
+        if high < low {
+            return Err(OutOfRange);
+        }
+        let register = mt9m001::CalThreshold::DEFAULT
+            .set_override_automatic_thres_hi_and_thres_lo_adjust(true);
+        let Ok(register) = register.set_thres_lo(low) else {
+            return Err(OutOfRange);
+        };
+        let Ok(register) = register.set_thres_hi(high) else {
+            return Err(OutOfRange);
+        };
+        Ok(Self { register })
+    }
+
+    pub const fn low(&self) -> u8 {
+        self.register.get_thres_lo()
+    }
+
+    pub const fn high(&self) -> u8 {
+        self.register.get_thres_hi()
+    }
+}
+
+/// How the sensor corrects its black level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlackLevelMode {
+    /// The sensor measures its dark columns and adjusts the analog offsets
+    /// while the ADC runs. Without thresholds it derives them from the gain.
+    Automatic(Option<Thresholds>),
+    /// Like `Automatic`, but the correction is applied all the time.
+    Continuous(Option<Thresholds>),
+    /// The analog offsets are programmed, in the order of the `parity`
+    /// planes.
+    Manual([i16; 4]),
+    /// No correction at all.
+    Disabled,
+}
+
+impl BlackLevelMode {
+    const fn registers(self) -> (mt9m001::CalCtrl, mt9m001::CalThreshold) {
+        let cal_threshold = match self {
+            BlackLevelMode::Automatic(Some(thresholds))
+            | BlackLevelMode::Continuous(Some(thresholds)) => thresholds.register,
+            _ => mt9m001::CalThreshold::DEFAULT,
+        };
+        let cal_control = match self {
+            BlackLevelMode::Automatic(_) => mt9m001::CalCtrl::DEFAULT
+                .set_start_a_new_running_digitally_filtered_average_for_the_black_level(true),
+            BlackLevelMode::Continuous(_) => mt9m001::CalCtrl::DEFAULT
+                .set_apply_black_level_calibration_continuously(true)
+                .set_start_a_new_running_digitally_filtered_average_for_the_black_level(true),
+            BlackLevelMode::Manual(_) => {
+                mt9m001::CalCtrl::DEFAULT.set_manual_override_of_black_level_correction(true)
+            }
+            BlackLevelMode::Disabled => {
+                mt9m001::CalCtrl::DEFAULT.set_disable_black_level_correction(true)
+            }
+        };
+        (cal_control, cal_threshold)
+    }
+}
+
+pub struct Sensor<I2C: I2c, SP: OutputPin, TP: OutputPin, C: ClockControl, T: Timer> {
+    sensor_clock: C,
+    timer: T,
+    standby: SP,
+    trigger: TP,
+    mt9m001: MT9M001<I2C>,
+    preview: bool,
+    frames: u32,
+    parity: ParityCalibration,
+    black_level: BlackLevelMode,
+}
+
+impl<I2C, SP, TP, C, T> Sensor<I2C, SP, TP, C, T>
+where
+    I2C: I2c,
+    SP: OutputPin,
+    TP: OutputPin,
+    C: ClockControl,
+    T: Timer,
+{
+    pub fn new(sensor_clock: C, timer: T, i2c: I2C, standby: SP, trigger: TP) -> Self {
+        Self {
+            sensor_clock,
+            timer,
+            standby,
+            trigger,
+            mt9m001: MT9M001::new_cached(i2c),
+            preview: false,
+            frames: 0,
+            parity: ParityCalibration::NONE,
+            black_level: BlackLevelMode::Manual([0; 4]),
+        }
+    }
+
+    /// Selects between full resolution and preview frames for the following
+    /// captures.
+    pub fn set_preview(&mut self, preview: bool) {
+        self.preview = preview;
+    }
+
+    pub fn parity(&self) -> ParityCalibration {
+        self.parity
+    }
+
+    /// Sets the parity plane calibration. Its gains are programmed with
+    /// every capture; its offsets take effect through
+    /// `BlackLevelMode::Manual`.
+    pub fn set_parity(&mut self, parity: ParityCalibration) {
+        self.parity = parity;
+    }
+
+    pub fn black_level(&self) -> BlackLevelMode {
+        self.black_level
+    }
+
+    /// Sets how the black level is corrected, programmed by `init` or
+    /// `apply_black_level`.
+    pub fn set_black_level(&mut self, mode: BlackLevelMode) {
+        self.black_level = mode;
+    }
+
+    /// Programs the black level correction, and the offsets of
+    /// `BlackLevelMode::Manual`, without resetting the sensor like `init`.
+    pub fn apply_black_level(&mut self) -> Result<(), SensorError> {
+        self.wake()?;
+        let result = self.program_black_level();
+        self.sleep()?;
+        result
+    }
+
+    fn program_black_level(&mut self) -> Result<(), SensorError> {
+        if let BlackLevelMode::Manual(offsets) = self.black_level {
+            self.mt9m001
+                .set_even_row_even_column_analog_offset(AnalogOffset::new(
+                    offsets[parity::EVEN_ROW_EVEN_COLUMN],
+                )?)
+                .map_err(|_| SensorError::Spi)?;
+            self.mt9m001
+                .set_even_row_odd_column_analog_offset(AnalogOffset::new(
+                    offsets[parity::EVEN_ROW_ODD_COLUMN],
+                )?)
+                .map_err(|_| SensorError::Spi)?;
+            self.mt9m001
+                .set_odd_row_even_column_analog_offset(AnalogOffset::new(
+                    offsets[parity::ODD_ROW_EVEN_COLUMN],
+                )?)
+                .map_err(|_| SensorError::Spi)?;
+            self.mt9m001
+                .set_odd_row_odd_column_analog_offset(AnalogOffset::new(
+                    offsets[parity::ODD_ROW_ODD_COLUMN],
+                )?)
+                .map_err(|_| SensorError::Spi)?;
+        }
+
+        let (cal_control, cal_threshold) = self.black_level.registers();
+        self.mt9m001
+            .set_cal_threshold(&cal_threshold)
+            .map_err(|_| SensorError::Spi)?;
+        self.mt9m001
+            .set_cal_ctrl(&cal_control)
+            .map_err(|_| SensorError::Spi)
+    }
+
+    /// Reads the analog offsets in use, whether programmed or found by the
+    /// automatic correction, in the order of the `parity` planes.
+    fn black_level_offsets(&mut self) -> Result<[AnalogOffset; 4], SensorError> {
+        let mut offsets = [AnalogOffset::DEFAULT; 4];
+        offsets[parity::EVEN_ROW_EVEN_COLUMN] = self
+            .mt9m001
+            .get_even_row_even_column_analog_offset()
+            .map_err(|_| SensorError::Spi)?;
+        offsets[parity::EVEN_ROW_ODD_COLUMN] = self
+            .mt9m001
+            .get_even_row_odd_column_analog_offset()
+            .map_err(|_| SensorError::Spi)?;
+        offsets[parity::ODD_ROW_EVEN_COLUMN] = self
+            .mt9m001
+            .get_odd_row_even_column_analog_offset()
+            .map_err(|_| SensorError::Spi)?;
+        offsets[parity::ODD_ROW_ODD_COLUMN] = self
+            .mt9m001
+            .get_odd_row_odd_column_analog_offset()
+            .map_err(|_| SensorError::Spi)?;
+        Ok(offsets)
+    }
+
+    fn wake(&mut self) -> Result<(), SensorError> {
+        self.sensor_clock.enable();
+        self.standby
+            .set_low()
+            .map_err(|_| SensorError::StandbyError)?;
+        self.mt9m001
+            .modify_output_control(|output_control| output_control.set_chip_enable(true))
+            .map_err(|_| SensorError::Spi)?;
+        self.timer.delay_ms(1);
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), SensorError> {
+        self.mt9m001
+            .modify_output_control(|output_control| output_control.set_chip_enable(false))
+            .map_err(|_| SensorError::Spi)?;
+        self.timer.delay_ms(1);
+        self.standby
+            .set_high()
+            .map_err(|_| SensorError::StandbyError)?;
+        self.sensor_clock.disable();
+        Ok(())
+    }
+
+    pub fn init(&mut self) -> Result<(), SensorError> {
+        self.wake()?;
+
+        self.mt9m001.soft_reset().map_err(|_| SensorError::Spi)?;
+
+        let read_options_1 = mt9m001::ReadOptions1::DEFAULT.set_snapshot_mode(true);
+        self.mt9m001
+            .set_read_options_1(&read_options_1)
+            .map_err(|_| SensorError::Spi)?;
+
+        self.program_black_level()?;
+
+        //let read_options_2 = mt9m001::ReadOptions2::DEFAULT.set_raw_data_output_mode(true);
+        //self.mt9m001.set_read_options_2(&read_options_2)?;
+
+        self.mt9m001
+            .set_column_start(ColumnStart::new(0)?)
+            .map_err(|_| SensorError::Spi)?;
+        // Column Size holds the number of columns read out minus one and has
+        // to be odd, so it reads out the whole frame buffer width.
+        self.mt9m001
+            .set_column_size(ColumnSize::new(geometry::READOUT.width() - 1)?)
+            .map_err(|_| SensorError::Spi)?;
+        self.mt9m001
+            .set_row_start(RowStart::new(0)?)
+            .map_err(|_| SensorError::Spi)?;
+        self.mt9m001
+            .set_row_size(RowSize::new(HEIGHT)?)
+            .map_err(|_| SensorError::Spi)?;
+        self.mt9m001
+            .set_horizontal_blanking(HorizontalBlanking::new(0)?)
+            .map_err(|_| SensorError::Spi)?;
+        self.mt9m001
+            .set_vertical_blanking(VerticalBlanking::new(0)?)
+            .map_err(|_| SensorError::Spi)?;
+
+        self.sleep()?;
+
+        Ok(())
+    }
+
+    /// Overrides the plane gains the global gain has set.
+    fn set_plane_gains(&mut self, gain: Gain) -> Result<(), SensorError> {
+        let register = |plane| mt9m001::Gain::new(self.parity.plane_gain(plane, gain).register());
+        self.mt9m001
+            .set_even_row_even_column_gain(register(parity::EVEN_ROW_EVEN_COLUMN)?)
+            .map_err(|_| SensorError::Spi)?;
+        self.mt9m001
+            .set_even_row_odd_column_gain(register(parity::EVEN_ROW_ODD_COLUMN)?)
+            .map_err(|_| SensorError::Spi)?;
+        self.mt9m001
+            .set_odd_row_even_column_gain(register(parity::ODD_ROW_EVEN_COLUMN)?)
+            .map_err(|_| SensorError::Spi)?;
+        self.mt9m001
+            .set_odd_row_odd_column_gain(register(parity::ODD_ROW_ODD_COLUMN)?)
+            .map_err(|_| SensorError::Spi)
+    }
+
+    /// Exposes and reads out a frame with `transfer_fn` and returns its
+    /// result along with the settings the sensor actually used.
+    pub fn configure_and_capture<R, F: FnOnce() -> R>(
+        &mut self,
+        gain: Gain,
+        exposure: Exposure,
+        transfer_fn: F,
+    ) -> Result<(R, CaptureInfo), SensorError> {
+        self.wake()?;
+
+        let preview = self.preview;
+        self.mt9m001
+            .modify_read_options_1(|read_options_1| {
+                read_options_1
+                    .set_snapshot_mode(true)
+                    .set_column_skip_8(preview)
+                    .set_row_skip_8(preview)
+            })
+            .map_err(|_| SensorError::Spi)?;
+
+        // Set gain
+        self.mt9m001
+            .set_global_gain(mt9m001::Gain::new(gain.register())?)
+            .map_err(|_| SensorError::Spi)?;
+        if self.parity.gains != ParityCalibration::NONE.gains {
+            self.set_plane_gains(gain)?;
+        }
+
+        // Set shutter speed
+        let timing = RowTiming {
+            column_size: self
+                .mt9m001
+                .get_column_size()
+                .map_err(|_| SensorError::Spi)?
+                .get(),
+            horizontal_blanking: self
+                .mt9m001
+                .get_horizontal_blanking()
+                .map_err(|_| SensorError::Spi)?
+                .get(),
+            shutter_delay: self
+                .mt9m001
+                .get_shutter_delay()
+                .map_err(|_| SensorError::Spi)?
+                .get(),
+        };
+        let timing = if self.preview {
+            timing.column_skip_8()
+        } else {
+            timing
+        };
+        let shutter_width = match exposure.shutter_width(&timing, FREQUENCY) {
+            Ok(shutter_width) => shutter_width,
+            Err(e) => {
+                self.sleep()?;
+                return Err(e.into());
+            }
+        };
+        self.mt9m001
+            .set_shutter_width(ShutterWidth::new(shutter_width)?)
+            .map_err(|_| SensorError::Spi)?;
+
+        let window = Window {
+            column_start: self
+                .mt9m001
+                .get_column_start()
+                .map_err(|_| SensorError::Spi)?
+                .get(),
+            row_start: self
+                .mt9m001
+                .get_row_start()
+                .map_err(|_| SensorError::Spi)?
+                .get(),
+            column_size: self
+                .mt9m001
+                .get_column_size()
+                .map_err(|_| SensorError::Spi)?
+                .get(),
+            row_size: self
+                .mt9m001
+                .get_row_size()
+                .map_err(|_| SensorError::Spi)?
+                .get(),
+        };
+
+        // Trigger...
+        let timestamp_us = self.timer.now_us();
+        self.trigger
+            .set_high()
+            .map_err(|_| SensorError::TriggerError)?;
+        self.trigger
+            .set_low()
+            .map_err(|_| SensorError::TriggerError)?;
+
+        // Capture here...
+        let result = transfer_fn();
+
+        let black_level_offsets = self.black_level_offsets()?;
+
+        self.sleep()?;
+
+        self.frames = self.frames.wrapping_add(1);
+        let info = CaptureInfo {
+            integration_us: timing.integration_us(shutter_width, FREQUENCY),
+            row_time: timing.row_time(),
+            shutter_width,
+            gain,
+            window,
+            frame: self.frames,
+            timestamp_us,
+            black_level_offsets,
+        };
+        Ok((result, info))
+    }
+}
+
+#[derive(Debug)]
+pub enum SensorError {
+    Spi,
+    TriggerError,
+    StandbyError,
+    OutOfRange,
+}
+
+impl From<OutOfRange> for SensorError {
+    fn from(_: OutOfRange) -> Self {
+        SensorError::OutOfRange
+    }
+}
+
+impl From<InvalidValue> for SensorError {
+    fn from(_: InvalidValue) -> Self {
+        SensorError::OutOfRange
+    }
+}
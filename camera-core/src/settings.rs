@@ -0,0 +1,539 @@
+//! Camera settings kept in the F-RAM.
+//!
+//! The settings are stored twice, in two slots. Every record has a header
+//! with a magic number, the layout version, the payload length and a
+//! sequence number, and ends with a CRC-32 of everything before it. A store
+//! overwrites the slot which does not hold the latest record and gives it the
+//! next sequence number, so an interrupted store leaves the previous record
+//! intact. Loading picks the valid record with the highest sequence number.
+
+use crate::{
+    controls::ExposureSettings,
+    crc::crc32,
+    exposure::{Exposure, Gain},
+    geometry::Framing,
+    naming::FileNaming,
+    parity::ParityCalibration,
+    platform::{PersistentMemory, RECORD_PROTECTION},
+    sensor::{BlackLevelMode, Thresholds},
+    tiff::{Dng, FileFormat, Justification, SampleLayout},
+};
+
+const MAGIC: [u8; 4] = *b"MKST";
+/// Layout version of the payload. Bump it when the payload changes and
+/// decode the previous layouts in `decode`.
+///
+/// 1. Exposure, file format, framing, naming and parity calibration.
+/// 2. Adds the black level mode.
+const VERSION: u16 = 2;
+
+const SLOTS: [u16; 2] = [0x0600, 0x0680];
+const SLOT_BYTES: usize = 0x80;
+const _: () = assert!(SLOTS[0] >= RECORD_PROTECTION.start());
+const HEADER_BYTES: usize = 12;
+const PAYLOAD_BYTES: usize = 35;
+const CRC_BYTES: usize = 4;
+const RECORD_BYTES: usize = HEADER_BYTES + PAYLOAD_BYTES + CRC_BYTES;
+const _: () = assert!(RECORD_BYTES <= SLOT_BYTES);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CameraSettings {
+    /// Where the auto exposure starts metering from.
+    pub exposure: ExposureSettings,
+    pub sample_layout: SampleLayout,
+    /// Linearization tables are not stored; loaded DNG settings have none.
+    pub file_format: FileFormat<'static>,
+    pub framing: Framing,
+    pub file_naming: FileNaming,
+    pub calibration: ParityCalibration,
+    /// Manual offsets are not stored; loaded settings use the offsets of
+    /// `calibration`.
+    pub black_level: BlackLevelMode,
+}
+
+impl CameraSettings {
+    fn encode(&self) -> [u8; PAYLOAD_BYTES] {
+        let mut writer = Writer::<PAYLOAD_BYTES>::new();
+        writer.u32(self.exposure.exposure.as_micros());
+        writer.u16(self.exposure.gain.eighths());
+        writer.u8(match self.sample_layout {
+            SampleLayout::Packed10 => 0,
+            SampleLayout::Unpacked16(Justification::Left) => 1,
+            SampleLayout::Unpacked16(Justification::Right) => 2,
+        });
+        match self.file_format {
+            FileFormat::Tiff => {
+                writer.u8(0);
+                writer.u16(0);
+            }
+            FileFormat::Dng(dng) => {
+                writer.u8(1);
+                writer.u16(dng.black_level);
+            }
+        }
+        writer.u8(match self.framing {
+            Framing::Crop => 0,
+            Framing::Keep => 1,
+        });
+        writer.bytes(&self.file_naming.prefix);
+        for offset in self.calibration.offsets {
+            writer.u16(offset as u16);
+        }
+        for gain in self.calibration.gains {
+            writer.u16(gain);
+        }
+        writer.u16(self.calibration.black_level);
+        let (mode, thresholds) = match self.black_level {
+            BlackLevelMode::Manual(_) => (0, None),
+            BlackLevelMode::Automatic(thresholds) => (1, thresholds),
+            BlackLevelMode::Continuous(thresholds) => (2, thresholds),
+            BlackLevelMode::Disabled => (3, None),
+        };
+        writer.u8(mode);
+        match thresholds {
+            Some(thresholds) => {
+                writer.u8(1);
+                writer.u8(thresholds.low());
+                writer.u8(thresholds.high());
+            }
+            None => writer.bytes(&[0; 3]),
+        }
+        writer.bytes
+    }
+
+    fn decode(version: u16, payload: &[u8]) -> Result<Self, SettingsError> {
+        let mut reader = Reader { bytes: payload };
+        let mut settings = match version {
+            1 | 2 => Self::decode_v1(&mut reader)?,
+            _ => return Err(SettingsError::Version),
+        };
+        if version >= 2 {
+            settings.black_level = match (reader.u8()?, Self::decode_thresholds(&mut reader)?) {
+                (0, _) => settings.black_level,
+                (1, thresholds) => BlackLevelMode::Automatic(thresholds),
+                (2, thresholds) => BlackLevelMode::Continuous(thresholds),
+                (3, _) => BlackLevelMode::Disabled,
+                _ => return Err(SettingsError::Value),
+            };
+        }
+        Ok(settings)
+    }
+
+    /// Decodes the layout of version 1, which later versions extend. The
+    /// firmware of version 1 always programmed the calibrated offsets.
+    fn decode_v1(reader: &mut Reader) -> Result<Self, SettingsError> {
+        let exposure = ExposureSettings {
+            exposure: Exposure::from_micros(reader.u32()?).map_err(|_| SettingsError::Value)?,
+            gain: Gain::new(reader.u16()? as f32 / 8.0).map_err(|_| SettingsError::Value)?,
+        };
+        let sample_layout = match reader.u8()? {
+            0 => SampleLayout::Packed10,
+            1 => SampleLayout::Unpacked16(Justification::Left),
+            2 => SampleLayout::Unpacked16(Justification::Right),
+            _ => return Err(SettingsError::Value),
+        };
+        let (format, black_level) = (reader.u8()?, reader.u16()?);
+        let file_format = match format {
+            0 => FileFormat::Tiff,
+            1 => FileFormat::Dng(Dng {
+                black_level,
+                linearization_table: None,
+            }),
+            _ => return Err(SettingsError::Value),
+        };
+        let framing = match reader.u8()? {
+            0 => Framing::Crop,
+            1 => Framing::Keep,
+            _ => return Err(SettingsError::Value),
+        };
+        let file_naming =
+            FileNaming::new([reader.u8()?, reader.u8()?]).ok_or(SettingsError::Value)?;
+        let mut calibration = ParityCalibration::NONE;
+        for offset in &mut calibration.offsets {
+            *offset = reader.u16()? as i16;
+        }
+        for gain in &mut calibration.gains {
+            *gain = reader.u16()?;
+        }
+        calibration.black_level = reader.u16()?;
+
+        Ok(Self {
+            exposure,
+            sample_layout,
+            file_format,
+            framing,
+            file_naming,
+            calibration,
+            black_level: BlackLevelMode::Manual(calibration.offsets),
+        })
+    }
+
+    fn decode_thresholds(reader: &mut Reader) -> Result<Option<Thresholds>, SettingsError> {
+        let (present, low, high) = (reader.u8()?, reader.u8()?, reader.u8()?);
+        match present {
+            0 => Ok(None),
+            1 => Thresholds::new(low, high)
+                .map(Some)
+                .map_err(|_| SettingsError::Value),
+            _ => Err(SettingsError::Value),
+        }
+    }
+}
+
+/// Keeps track of which slot holds the latest record.
+pub struct SettingsStore {
+    /// Slot and sequence number of the latest record with a valid CRC.
+    latest: Option<(usize, u32)>,
+}
+
+impl SettingsStore {
+    /// Loads the latest valid settings, or returns `defaults` without any.
+    pub fn load<M: PersistentMemory>(
+        memory: &mut M,
+        defaults: CameraSettings,
+    ) -> Result<(Self, CameraSettings), SettingsError> {
+        let mut newest: Option<(usize, u32)> = None;
+        let mut loaded: Option<(u32, CameraSettings)> = None;
+        for (slot, address) in SLOTS.into_iter().enumerate() {
+            // Records of newer layouts may be longer.
+            let mut record = [0; SLOT_BYTES];
+            memory
+                .read(address, &mut record)
+                .map_err(|_| SettingsError::Fram)?;
+            let Some((version, sequence, payload)) = parse_record(&record) else {
+                continue;
+            };
+            if newest.is_none_or(|(_, newest)| is_after(sequence, newest)) {
+                newest = Some((slot, sequence));
+            }
+            if let Ok(settings) = CameraSettings::decode(version, payload)
+                && loaded.is_none_or(|(loaded, _)| is_after(sequence, loaded))
+            {
+                loaded = Some((sequence, settings));
+            }
+        }
+
+        // Stores never overwrite the newest record, even if this firmware
+        // cannot decode it.
+        let store = Self { latest: newest };
+        let settings = loaded.map_or(defaults, |(_, settings)| settings);
+        Ok((store, settings))
+    }
+
+    /// Writes `settings` into the slot which does not hold the latest
+    /// record.
+    pub fn store<M: PersistentMemory>(
+        &mut self,
+        memory: &mut M,
+        settings: &CameraSettings,
+    ) -> Result<(), SettingsError> {
+        let (slot, sequence) = match self.latest {
+            Some((slot, sequence)) => (1 - slot, sequence.wrapping_add(1)),
+            None => (0, 0),
+        };
+
+        let mut writer = Writer::<RECORD_BYTES>::new();
+        writer.bytes(&MAGIC);
+        writer.u16(VERSION);
+        writer.u16(PAYLOAD_BYTES as u16);
+        writer.u32(sequence);
+        writer.bytes(&settings.encode());
+        writer.u32(crc32(&writer.bytes[..RECORD_BYTES - CRC_BYTES]));
+
+        memory
+            .write(SLOTS[slot], &writer.bytes)
+            .map_err(|_| SettingsError::Fram)?;
+        self.latest = Some((slot, sequence));
+        Ok(())
+    }
+}
+
+/// Returns the version, sequence number and payload of a record with a
+/// valid header and CRC.
+fn parse_record(record: &[u8]) -> Option<(u16, u32, &[u8])> {
+    let mut reader = Reader { bytes: record };
+    if reader.take(MAGIC.len()).ok()? != MAGIC {
+        return None;
+    }
+    let version = reader.u16().ok()?;
+    let len = reader.u16().ok()? as usize;
+    let sequence = reader.u32().ok()?;
+    let payload = reader.take(len).ok()?;
+    let crc = reader.u32().ok()?;
+    if crc != crc32(&record[..HEADER_BYTES + len]) {
+        return None;
+    }
+    Some((version, sequence, payload))
+}
+
+/// Whether sequence number `a` was written after `b`, allowing for
+/// wrapping.
+const fn is_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+struct Writer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Writer<N> {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SettingsError> {
+        let (head, tail) = self
+            .bytes
+            .split_at_checked(len)
+            .ok_or(SettingsError::Value)?;
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, SettingsError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SettingsError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SettingsError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Fram,
+    /// A record has a layout this firmware does not know.
+    Version,
+    /// A record holds a value out of range.
+    Value,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{platform::Fram, sim::SimTimer};
+    use fm25l16b::{FM25L16B, sim::Simulator};
+    use std::vec::Vec;
+
+    fn settings() -> CameraSettings {
+        CameraSettings {
+            exposure: ExposureSettings {
+                exposure: Exposure::from_micros(2_500).unwrap(),
+                gain: Gain::new(2.5).unwrap(),
+            },
+            sample_layout: SampleLayout::Unpacked16(Justification::Right),
+            file_format: FileFormat::Dng(Dng {
+                black_level: 48,
+                linearization_table: None,
+            }),
+            framing: Framing::Keep,
+            file_naming: FileNaming::new(*b"XY").unwrap(),
+            calibration: ParityCalibration {
+                offsets: [-3, 5, 0, 12],
+                gains: [4000, 4100, 4096, 4200],
+                black_level: 40,
+            },
+            black_level: BlackLevelMode::Continuous(Some(Thresholds::new(4, 12).unwrap())),
+        }
+    }
+
+    fn defaults() -> CameraSettings {
+        CameraSettings {
+            exposure: ExposureSettings {
+                exposure: Exposure::from_micros(10_000).unwrap(),
+                gain: Gain::UNITY,
+            },
+            sample_layout: SampleLayout::Packed10,
+            file_format: FileFormat::Tiff,
+            framing: Framing::Crop,
+            file_naming: FileNaming::new(*b"IM").unwrap(),
+            calibration: ParityCalibration::NONE,
+            black_level: BlackLevelMode::Manual([0; 4]),
+        }
+    }
+
+    fn memory(chip: &mut Simulator) -> Fram<&mut Simulator, SimTimer> {
+        Fram::new(FM25L16B::new(chip), SimTimer::default())
+    }
+
+    fn load(chip: &mut Simulator) -> (SettingsStore, CameraSettings) {
+        SettingsStore::load(&mut memory(chip), defaults()).unwrap()
+    }
+
+    /// A record as `store` writes it, with any version and payload.
+    fn record(version: u16, sequence: u32, payload: &[u8]) -> Vec<u8> {
+        let mut record = Vec::from(MAGIC);
+        record.extend(version.to_le_bytes());
+        record.extend((payload.len() as u16).to_le_bytes());
+        record.extend(sequence.to_le_bytes());
+        record.extend(payload);
+        record.extend(crc32(&record).to_le_bytes());
+        record
+    }
+
+    fn put(chip: &mut Simulator, slot: usize, record: &[u8]) {
+        let address = SLOTS[slot] as usize;
+        chip.memory[address..address + record.len()].copy_from_slice(record);
+    }
+
+    #[test]
+    fn blank_memory_loads_defaults() {
+        let mut chip = Simulator::new();
+        let (store, settings) = load(&mut chip);
+        assert_eq!(store.latest, None);
+        assert_eq!(settings, defaults());
+    }
+
+    #[test]
+    fn stored_settings_load() {
+        let mut chip = Simulator::new();
+        let (mut store, _) = load(&mut chip);
+        store.store(&mut memory(&mut chip), &settings()).unwrap();
+        assert_eq!(load(&mut chip).1, settings());
+    }
+
+    #[test]
+    fn manual_black_level_uses_calibrated_offsets() {
+        let mut chip = Simulator::new();
+        let stored = CameraSettings {
+            black_level: BlackLevelMode::Manual([1, 2, 3, 4]),
+            ..settings()
+        };
+        let (mut store, _) = load(&mut chip);
+        store.store(&mut memory(&mut chip), &stored).unwrap();
+        assert_eq!(
+            load(&mut chip).1.black_level,
+            BlackLevelMode::Manual([-3, 5, 0, 12])
+        );
+    }
+
+    #[test]
+    fn stores_keep_the_records_protected() {
+        let mut chip = Simulator::new();
+        memory(&mut chip).protect().unwrap();
+        let (mut store, _) = load(&mut chip);
+        store.store(&mut memory(&mut chip), &settings()).unwrap();
+        assert_eq!(chip.status().block_protection, RECORD_PROTECTION);
+        assert!(
+            FM25L16B::new(&mut chip)
+                .write_bytes(SLOTS[0], &[0; RECORD_BYTES])
+                .is_err()
+        );
+        assert_eq!(load(&mut chip).1, settings());
+    }
+
+    #[test]
+    fn stores_alternate_between_slots() {
+        let mut chip = Simulator::new();
+        let (mut store, _) = load(&mut chip);
+        let mut expected = settings();
+        for (i, slot) in [0, 1, 0].into_iter().enumerate() {
+            expected.framing = [Framing::Crop, Framing::Keep][i % 2];
+            store.store(&mut memory(&mut chip), &expected).unwrap();
+            assert_eq!(store.latest, Some((slot, i as u32)));
+            let (loaded_store, loaded) = load(&mut chip);
+            assert_eq!(loaded_store.latest, store.latest);
+            assert_eq!(loaded, expected);
+        }
+    }
+
+    #[test]
+    fn interrupted_store_keeps_previous_settings() {
+        let mut chip = Simulator::new();
+        let (mut store, _) = load(&mut chip);
+        store.store(&mut memory(&mut chip), &defaults()).unwrap();
+        chip.write_budget = Some(RECORD_BYTES / 2);
+        store.store(&mut memory(&mut chip), &settings()).unwrap();
+        chip.write_budget = None;
+
+        let (mut store, loaded) = load(&mut chip);
+        assert_eq!(loaded, defaults());
+        // The torn record is overwritten next, not the intact one.
+        store.store(&mut memory(&mut chip), &settings()).unwrap();
+        assert_eq!(store.latest, Some((1, 1)));
+        assert_eq!(load(&mut chip).1, settings());
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        let mut chip = Simulator::new();
+        put(
+            &mut chip,
+            0,
+            &record(VERSION, u32::MAX, &defaults().encode()),
+        );
+        put(&mut chip, 1, &record(VERSION, 0, &settings().encode()));
+        let (store, loaded) = load(&mut chip);
+        assert_eq!(store.latest, Some((1, 0)));
+        assert_eq!(loaded, settings());
+    }
+
+    #[test]
+    fn version_1_records_load() {
+        let mut chip = Simulator::new();
+        let payload = settings().encode();
+        // Version 1 payloads end before the black level mode.
+        put(&mut chip, 0, &record(1, 7, &payload[..PAYLOAD_BYTES - 4]));
+        let (store, loaded) = load(&mut chip);
+        assert_eq!(store.latest, Some((0, 7)));
+        assert_eq!(
+            loaded,
+            CameraSettings {
+                black_level: BlackLevelMode::Manual([-3, 5, 0, 12]),
+                ..settings()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_versions_are_kept() {
+        let mut chip = Simulator::new();
+        put(&mut chip, 0, &record(VERSION, 3, &settings().encode()));
+        put(&mut chip, 1, &record(VERSION + 1, 4, &[0; 40]));
+        let (mut store, loaded) = load(&mut chip);
+        // The older record still loads, and the newer one is not
+        // overwritten.
+        assert_eq!(loaded, settings());
+        store.store(&mut memory(&mut chip), &defaults()).unwrap();
+        assert_eq!(store.latest, Some((0, 5)));
+        assert_eq!(load(&mut chip).1, defaults());
+        // Without a record it can decode, the defaults are used.
+        put(&mut chip, 0, &[0; RECORD_BYTES]);
+        let (store, loaded) = load(&mut chip);
+        assert_eq!(store.latest, Some((1, 4)));
+        assert_eq!(loaded, defaults());
+    }
+}
@@ -0,0 +1,517 @@
+//! A simulated board to run the camera on the host.
+//!
+//! Every part implements the trait or `embedded_hal` interface its
+//! counterpart on the board does, so a `Pipeline` built from them behaves
+//! like the camera. Parts which see the same hardware share it: clones of a
+//! `SimTimer` tell the same time, and the `SimFrameSource` renders frames
+//! from the registers of the simulated sensor behind the `SimI2c` it was
+//! created with.
+
+use core::{cell::Cell, cell::RefCell, convert::Infallible};
+use std::{collections::BTreeMap, rc::Rc, string::String, vec, vec::Vec};
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{self, InputPin, OutputPin},
+    i2c::{self, ErrorKind, I2c, Operation},
+};
+use fm25l16b::FM25L16B;
+use mt9m001::registers::{
+    COLUMN_SIZE, EVEN_ROW_EVEN_COLUMN_ANALOG_OFFSET, EVEN_ROW_EVEN_COLUMN_GAIN,
+    EVEN_ROW_ODD_COLUMN_ANALOG_OFFSET, EVEN_ROW_ODD_COLUMN_GAIN, HORIZONTAL_BLANKING,
+    ODD_ROW_EVEN_COLUMN_ANALOG_OFFSET, ODD_ROW_EVEN_COLUMN_GAIN, ODD_ROW_ODD_COLUMN_ANALOG_OFFSET,
+    ODD_ROW_ODD_COLUMN_GAIN, READ_OPTIONS_1, SHUTTER_DELAY, SHUTTER_WIDTH,
+};
+
+use crate::{
+    buffer,
+    controls::{DialInputs, DialPositions},
+    exposure::{Gain, RowTiming},
+    geometry,
+    packed::{self, GROUP_BYTES, GROUP_PIXELS},
+    platform::{ClockControl, Fram, FrameSource, Storage, Timer},
+    sensor,
+    tiff::DateTime,
+};
+
+/// The sensor clock, which only remembers whether it runs.
+#[derive(Debug, Default)]
+pub struct SimClock {
+    pub running: bool,
+}
+
+impl ClockControl for SimClock {
+    fn enable(&mut self) {
+        self.running = true;
+    }
+
+    fn disable(&mut self) {
+        self.running = false;
+    }
+}
+
+/// A timer whose time only passes in delays.
+#[derive(Debug, Default, Clone)]
+pub struct SimTimer {
+    now_ns: Rc<Cell<u64>>,
+}
+
+impl DelayNs for SimTimer {
+    fn delay_ns(&mut self, ns: u32) {
+        self.now_ns.set(self.now_ns.get() + ns as u64);
+    }
+}
+
+impl Timer for SimTimer {
+    fn now_us(&mut self) -> u64 {
+        self.now_ns.get() / 1000
+    }
+}
+
+/// A pin whose clones see the same level, so one clone can drive what
+/// another reads.
+#[derive(Debug, Default, Clone)]
+pub struct SimPin {
+    high: Rc<Cell<bool>>,
+}
+
+impl digital::ErrorType for SimPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for SimPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.high.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.high.set(true);
+        Ok(())
+    }
+}
+
+impl InputPin for SimPin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.high.get())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.high.get())
+    }
+}
+
+/// Registers of the planes in the order of the `parity` planes.
+const PLANE_GAINS: [u8; 4] = [
+    EVEN_ROW_EVEN_COLUMN_GAIN,
+    EVEN_ROW_ODD_COLUMN_GAIN,
+    ODD_ROW_EVEN_COLUMN_GAIN,
+    ODD_ROW_ODD_COLUMN_GAIN,
+];
+const PLANE_OFFSETS: [u8; 4] = [
+    EVEN_ROW_EVEN_COLUMN_ANALOG_OFFSET,
+    EVEN_ROW_ODD_COLUMN_ANALOG_OFFSET,
+    ODD_ROW_EVEN_COLUMN_ANALOG_OFFSET,
+    ODD_ROW_ODD_COLUMN_ANALOG_OFFSET,
+];
+
+/// The sensor, simulated by `mt9m001::sim::Simulator`, behind a handle
+/// whose clones reach the same chip.
+#[derive(Default, Clone)]
+pub struct SimI2c {
+    sensor: Rc<RefCell<mt9m001::sim::Simulator>>,
+}
+
+impl SimI2c {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, address: u8) -> u16 {
+        self.sensor.borrow().register(address)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sensor.borrow().is_enabled()
+    }
+}
+
+impl i2c::ErrorType for SimI2c {
+    type Error = ErrorKind;
+}
+
+impl I2c for SimI2c {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), ErrorKind> {
+        self.sensor.borrow_mut().transaction(address, operations)
+    }
+}
+
+/// Renders frames from the sensor registers: every pixel reads the black
+/// level plus its plane's analog offset, plus the light it integrated
+/// amplified by its plane's gain and dimmed by the vignetting.
+pub struct SimFrameSource {
+    sensor: SimI2c,
+    buffer: Vec<u32>,
+    /// Words of the frame being received.
+    receiving: Option<usize>,
+    /// Signal per microsecond of integration at unity gain, in ADC steps.
+    pub light: f32,
+    pub black_level: u16,
+    /// How strongly each plane responds to light, in the order of the
+    /// `parity` planes.
+    pub plane_response: [f32; 4],
+    /// Share of the light lost in the corners of the frame, falling off with
+    /// the square of the distance from its centre like a lens vignettes.
+    pub vignetting: f32,
+}
+
+/// A frame that does not fit into the frame buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTooLarge;
+
+impl SimFrameSource {
+    /// A frame source with a buffer of `words` words rendering from
+    /// `sensor`.
+    pub fn new(sensor: SimI2c, words: usize) -> Self {
+        Self {
+            sensor,
+            buffer: vec![0; words],
+            receiving: None,
+            light: 0.0,
+            black_level: 0,
+            plane_response: [1.0; 4],
+            vignetting: 0.0,
+        }
+    }
+
+    fn render(&mut self, words: usize) {
+        let read_options_1 = mt9m001::ReadOptions1::new(self.sensor.register(READ_OPTIONS_1));
+        let timing = RowTiming {
+            column_size: self.sensor.register(COLUMN_SIZE),
+            horizontal_blanking: self.sensor.register(HORIZONTAL_BLANKING),
+            shutter_delay: self.sensor.register(SHUTTER_DELAY),
+        };
+        let (timing, width) = if read_options_1.get_column_skip_8() {
+            (timing.column_skip_8(), sensor::PREVIEW_WIDTH)
+        } else {
+            (timing, geometry::READOUT.width())
+        };
+        let integration_us =
+            timing.integration_us(self.sensor.register(SHUTTER_WIDTH), sensor::FREQUENCY);
+        let darks: [f32; 4] = core::array::from_fn(|plane| {
+            let offset =
+                mt9m001::AnalogOffset::from_register(self.sensor.register(PLANE_OFFSETS[plane]))
+                    .get();
+            self.black_level as f32 + offset as f32
+        });
+        let signals: [f32; 4] = core::array::from_fn(|plane| {
+            let gain = Gain::from_register(self.sensor.register(PLANE_GAINS[plane])).as_f32();
+            self.light * integration_us as f32 * gain * self.plane_response[plane]
+        });
+
+        let height = words * 32 / 10 / width as usize;
+        let (center_x, center_y) = (width as f32 / 2.0, height as f32 / 2.0);
+        let corner = center_x * center_x + center_y * center_y;
+        let bytes = buffer::as_bytes_mut(&mut self.buffer[..words]);
+        for (group_index, group) in bytes.chunks_exact_mut(GROUP_BYTES).enumerate() {
+            let pixels = core::array::from_fn(|i| {
+                let pixel = group_index * GROUP_PIXELS + i;
+                let (x, y) = (pixel % width as usize, pixel / width as usize);
+                let plane = (y & 1) * 2 + (x & 1);
+                let (dx, dy) = (x as f32 + 0.5 - center_x, y as f32 + 0.5 - center_y);
+                let light = 1.0 - self.vignetting * (dx * dx + dy * dy) / corner;
+                (darks[plane] + signals[plane] * light).clamp(0.0, 1023.0) as u16
+            });
+            group.copy_from_slice(&packed::pack(pixels));
+        }
+    }
+}
+
+impl FrameSource for SimFrameSource {
+    type Error = FrameTooLarge;
+
+    fn start(&mut self, words: usize) -> Result<(), FrameTooLarge> {
+        if words > self.buffer.len() {
+            return Err(FrameTooLarge);
+        }
+        self.receiving = Some(words);
+        Ok(())
+    }
+
+    fn wait(&mut self) -> Result<(), FrameTooLarge> {
+        if let Some(words) = self.receiving.take() {
+            self.render(words);
+        }
+        Ok(())
+    }
+
+    fn frame(&self) -> &[u32] {
+        match self.receiving {
+            Some(_) => &[],
+            None => &self.buffer,
+        }
+    }
+
+    fn frame_mut(&mut self) -> &mut [u32] {
+        match self.receiving {
+            Some(_) => &mut [],
+            None => &mut self.buffer,
+        }
+    }
+}
+
+/// Exposure dials whose clones see the same positions, so they can be
+/// turned after being handed over.
+#[derive(Debug, Clone)]
+pub struct SimDials {
+    positions: Rc<Cell<DialPositions>>,
+}
+
+impl SimDials {
+    pub fn new(positions: DialPositions) -> Self {
+        Self {
+            positions: Rc::new(Cell::new(positions)),
+        }
+    }
+
+    pub fn turn(&self, positions: DialPositions) {
+        self.positions.set(positions);
+    }
+}
+
+impl DialInputs for SimDials {
+    type Error = Infallible;
+
+    fn sample(&mut self) -> Result<DialPositions, Infallible> {
+        Ok(self.positions.get())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimStorageError {
+    FileExists,
+}
+
+/// Files kept in memory, stamped with a fixed time if one is given.
+#[derive(Debug)]
+pub struct SimStorage {
+    pub files: BTreeMap<String, Vec<u8>>,
+    pub date_time: Option<DateTime>,
+}
+
+impl SimStorage {
+    pub fn new(date_time: Option<DateTime>) -> Self {
+        Self {
+            files: BTreeMap::new(),
+            date_time,
+        }
+    }
+}
+
+impl Storage for SimStorage {
+    type Error = SimStorageError;
+
+    fn now(&self) -> Option<DateTime> {
+        self.date_time
+    }
+
+    fn create_file<F>(&mut self, file_name: &str, write: F) -> Result<(), SimStorageError>
+    where
+        F: FnOnce(
+            &mut dyn FnMut(&[u8]) -> Result<(), SimStorageError>,
+        ) -> Result<(), SimStorageError>,
+    {
+        if self.files.contains_key(file_name) {
+            return Err(SimStorageError::FileExists);
+        }
+        let mut contents = Vec::new();
+        let result = write(&mut |bytes| {
+            contents.extend_from_slice(bytes);
+            Ok(())
+        });
+        self.files.insert(file_name.into(), contents);
+        result
+    }
+
+    fn write_file(&mut self, file_name: &str, bytes: &[u8]) -> Result<(), SimStorageError> {
+        self.files.insert(file_name.into(), bytes.into());
+        Ok(())
+    }
+
+    fn read_file(
+        &mut self,
+        file_name: &str,
+        buffer: &mut [u8],
+    ) -> Result<Option<usize>, SimStorageError> {
+        let Some(contents) = self.files.get(file_name) else {
+            return Ok(None);
+        };
+        let len = contents.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&contents[..len]);
+        Ok(Some(len))
+    }
+}
+
+/// An F-RAM simulated down to its SPI transactions.
+pub type SimMemory = Fram<fm25l16b::sim::Simulator, SimTimer>;
+
+/// A blank F-RAM which waits for `timer` to wake up.
+pub fn memory(timer: SimTimer) -> SimMemory {
+    Fram::new(FM25L16B::new(fm25l16b::sim::Simulator::new()), timer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        exposure::Exposure,
+        parity::{self, ParityCalibration},
+        sensor::{BlackLevelMode, Sensor, Window},
+    };
+    use mt9m001::registers::{
+        CAL_CTRL, COLUMN_START, GLOBAL_GAIN, ROW_SIZE, ROW_START, SENSOR_ADDRESS,
+    };
+
+    type SimSensor = Sensor<SimI2c, SimPin, SimPin, SimClock, SimTimer>;
+
+    /// A sensor on the simulated board, with a handle on its registers and
+    /// its standby pin.
+    fn sensor() -> (SimSensor, SimI2c, SimPin) {
+        let i2c = SimI2c::new();
+        let standby = SimPin::default();
+        let sensor = Sensor::new(
+            SimClock::default(),
+            SimTimer::default(),
+            i2c.clone(),
+            standby.clone(),
+            SimPin::default(),
+        );
+        (sensor, i2c, standby)
+    }
+
+    fn capture(sensor: &mut SimSensor, i2c: &SimI2c, gain: f32, us: u32) -> sensor::CaptureInfo {
+        let exposure = Exposure::from_micros(us).unwrap();
+        let (enabled, info) = sensor
+            .configure_and_capture(Gain::new(gain).unwrap(), exposure, || i2c.is_enabled())
+            .unwrap();
+        assert!(enabled, "the frame is read out while the sensor is awake");
+        info
+    }
+
+    #[test]
+    fn init_programs_the_readout_and_sleeps() {
+        let (mut sensor, i2c, mut standby) = sensor();
+        sensor.set_black_level(BlackLevelMode::Manual([1, -2, 3, -4]));
+        sensor.init().unwrap();
+
+        assert!(!i2c.is_enabled());
+        assert!(standby.is_high().unwrap());
+        assert_eq!(i2c.register(COLUMN_START), 0);
+        assert_eq!(i2c.register(ROW_START), 0);
+        assert_eq!(i2c.register(COLUMN_SIZE), geometry::READOUT.width() - 1);
+        assert_eq!(i2c.register(ROW_SIZE), geometry::READOUT.height());
+        assert_eq!(i2c.register(HORIZONTAL_BLANKING), 0);
+        assert!(mt9m001::ReadOptions1::new(i2c.register(READ_OPTIONS_1)).get_snapshot_mode());
+        assert!(
+            mt9m001::CalCtrl::new(i2c.register(CAL_CTRL))
+                .get_manual_override_of_black_level_correction()
+        );
+        let offsets = PLANE_OFFSETS
+            .map(|address| mt9m001::AnalogOffset::from_register(i2c.register(address)).get());
+        assert_eq!(offsets, [1, -2, 3, -4]);
+    }
+
+    #[test]
+    fn capture_reports_the_registers_it_used() {
+        let (mut sensor, i2c, mut standby) = sensor();
+        sensor.init().unwrap();
+        let info = capture(&mut sensor, &i2c, 2.0, 10_000);
+
+        assert!(!i2c.is_enabled());
+        assert!(standby.is_high().unwrap());
+        assert!(!mt9m001::ReadOptions1::new(i2c.register(READ_OPTIONS_1)).get_column_skip_8());
+        assert_eq!(info.gain, Gain::new(2.0).unwrap());
+        assert_eq!(i2c.register(GLOBAL_GAIN), info.gain.register());
+        assert_eq!(i2c.register(SHUTTER_WIDTH), info.shutter_width);
+        let row_us = info.row_time * 1_000_000 / sensor::FREQUENCY;
+        assert!(info.integration_us.abs_diff(10_000) <= row_us);
+        assert_eq!(
+            info.window,
+            Window {
+                column_start: 0,
+                row_start: 0,
+                column_size: geometry::READOUT.width() - 1,
+                row_size: geometry::READOUT.height(),
+            }
+        );
+        assert_eq!(info.frame, 1);
+        assert_eq!(info.black_level_offsets.map(|offset| offset.get()), [0; 4]);
+
+        let next = capture(&mut sensor, &i2c, 1.0, 1_000);
+        assert_eq!(next.frame, 2);
+        assert!(next.timestamp_us > info.timestamp_us);
+        assert!(next.shutter_width < info.shutter_width);
+    }
+
+    #[test]
+    fn preview_skips_rows_and_columns() {
+        let (mut sensor, i2c, _) = sensor();
+        sensor.init().unwrap();
+        let full = capture(&mut sensor, &i2c, 1.0, 10_000);
+        sensor.set_preview(true);
+        let preview = capture(&mut sensor, &i2c, 1.0, 10_000);
+
+        let read_options_1 = mt9m001::ReadOptions1::new(i2c.register(READ_OPTIONS_1));
+        assert!(read_options_1.get_snapshot_mode());
+        assert!(read_options_1.get_column_skip_8());
+        assert!(read_options_1.get_row_skip_8());
+        // Rows of a preview are read out faster, so more of them expose the
+        // same time.
+        assert!(preview.row_time < full.row_time);
+        assert!(preview.shutter_width > full.shutter_width);
+    }
+
+    #[test]
+    fn parity_gains_override_the_plane_gains() {
+        let (mut sensor, i2c, _) = sensor();
+        let mut parity = ParityCalibration::NONE;
+        parity.gains[parity::ODD_ROW_ODD_COLUMN] = parity::UNITY * 2;
+        sensor.set_parity(parity);
+        sensor.init().unwrap();
+        let info = capture(&mut sensor, &i2c, 2.0, 10_000);
+
+        let gains = PLANE_GAINS.map(|address| i2c.register(address));
+        let doubled = Gain::new(4.0).unwrap().register();
+        assert_eq!(gains[parity::ODD_ROW_ODD_COLUMN], doubled);
+        assert_eq!(gains[parity::EVEN_ROW_EVEN_COLUMN], info.gain.register());
+    }
+
+    #[test]
+    fn automatic_black_level_offsets_are_reported() {
+        let (mut sensor, i2c, _) = sensor();
+        sensor.set_black_level(BlackLevelMode::Automatic(None));
+        sensor.init().unwrap();
+        let cal_ctrl = mt9m001::CalCtrl::new(i2c.register(CAL_CTRL));
+        assert!(!cal_ctrl.get_manual_override_of_black_level_correction());
+
+        // The correction adjusts the offsets while the frame is read out.
+        let found = mt9m001::AnalogOffset::new(-7).unwrap().register();
+        let mut sensor_i2c = i2c.clone();
+        let exposure = Exposure::from_micros(10_000).unwrap();
+        let (_, info) = sensor
+            .configure_and_capture(Gain::UNITY, exposure, || {
+                for address in PLANE_OFFSETS {
+                    let [hi, lo] = found.to_be_bytes();
+                    sensor_i2c
+                        .write(SENSOR_ADDRESS, &[address, hi, lo])
+                        .unwrap();
+                }
+            })
+            .unwrap();
+        assert_eq!(info.black_level_offsets.map(|offset| offset.get()), [-7; 4]);
+    }
+}
@@ -0,0 +1,990 @@
+use crate::{
+    geometry::Area,
+    metering::WHITE_LEVEL,
+    packed::{self, GROUP_BYTES, GROUP_PIXELS},
+};
+
+const TAG_NEW_SUBFILE_TYPE: u16 = 254;
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_HEIGHT: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC_INTERPRETATION: u16 = 262;
+const TAG_FILL_ORDER: u16 = 266;
+const TAG_MAKE: u16 = 271;
+const TAG_MODEL: u16 = 272;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_ORIENTATION: u16 = 274;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_STRIP_BYTE_COUNT: u16 = 279;
+const TAG_SOFTWARE: u16 = 305;
+const TAG_DATE_TIME: u16 = 306;
+const TAG_SAMPLE_FORMAT: u16 = 339;
+const TAG_EXPOSURE_TIME: u16 = 33434;
+const TAG_EXIF_IFD: u16 = 34665;
+const TAG_ISO_SPEED_RATINGS: u16 = 34855;
+const TAG_EXIF_VERSION: u16 = 36864;
+const TAG_DATE_TIME_ORIGINAL: u16 = 36867;
+const TAG_IMAGE_NUMBER: u16 = 37393;
+const TAG_DNG_VERSION: u16 = 50706;
+const TAG_DNG_BACKWARD_VERSION: u16 = 50707;
+const TAG_UNIQUE_CAMERA_MODEL: u16 = 50708;
+const TAG_LINEARIZATION_TABLE: u16 = 50712;
+const TAG_BLACK_LEVEL: u16 = 50714;
+const TAG_WHITE_LEVEL: u16 = 50717;
+const TAG_DEFAULT_CROP_ORIGIN: u16 = 50719;
+const TAG_DEFAULT_CROP_SIZE: u16 = 50720;
+const TAG_ACTIVE_AREA: u16 = 50829;
+/// Private tag holding the sensor registers listed in [`Registers`].
+const TAG_SENSOR_REGISTERS: u16 = 65000;
+/// Private tag holding the row time, the frame number since power-up and the
+/// trigger timestamp as four LONGs; the timestamp takes the last two, low
+/// word first.
+const TAG_SENSOR_CAPTURE: u16 = 65001;
+
+/// Raw MT9M001 register values a frame was captured with, in the order they
+/// are stored in the private sensor registers tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub shutter_width: u16,
+    pub global_gain: u16,
+    pub column_start: u16,
+    pub row_start: u16,
+    pub column_size: u16,
+    pub row_size: u16,
+    /// Analog offset registers, even row first, even column first.
+    pub analog_offsets: [u16; 4],
+}
+
+impl Registers {
+    const COUNT: usize = 10;
+
+    const fn to_le_bytes(self) -> [u8; Self::COUNT * 2] {
+        let [o0, o1, o2, o3] = self.analog_offsets;
+        let words = [
+            self.shutter_width,
+            self.global_gain,
+            self.column_start,
+            self.row_start,
+            self.column_size,
+            self.row_size,
+            o0,
+            o1,
+            o2,
+            o3,
+        ];
+        let mut bytes = [0; Self::COUNT * 2];
+        let mut i = 0;
+        while i < Self::COUNT {
+            let [lo, hi] = words[i].to_le_bytes();
+            bytes[2 * i] = lo;
+            bytes[2 * i + 1] = hi;
+            i += 1;
+        }
+        bytes
+    }
+}
+
+/// How the 10-bit pixels are stored in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleLayout {
+    /// The packed PIO stream as is, with `BitsPerSample = 10`.
+    Packed10,
+    /// Every pixel in its own 16-bit sample, which more tools can open.
+    Unpacked16(Justification),
+}
+
+/// Where the 10 bits sit in a 16-bit sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Justification {
+    /// In the top bits, so the samples span the full 16-bit range.
+    Left,
+    /// In the bottom bits, so the samples keep their 10-bit values.
+    Right,
+}
+
+impl SampleLayout {
+    const fn bits_per_sample(self) -> u16 {
+        match self {
+            SampleLayout::Packed10 => 10,
+            SampleLayout::Unpacked16(_) => 16,
+        }
+    }
+
+    /// Left shift of the 10-bit pixel values in the samples.
+    const fn shift(self) -> u32 {
+        match self {
+            SampleLayout::Unpacked16(Justification::Left) => 6,
+            _ => 0,
+        }
+    }
+
+    /// Size in the file of the samples of `area`.
+    const fn strip_byte_count(self, area: &Area) -> u32 {
+        area.width() as u32 * area.height() as u32 * self.bits_per_sample() as u32 / 8
+    }
+}
+
+/// A packed frame and the part of it which is written to the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Image<'a> {
+    pub frame: &'a [u8],
+    /// Pixels per row of `frame`.
+    pub frame_width: u16,
+    /// The part of `frame` written to the file. It has to be group aligned.
+    pub area: Area,
+    /// The image pixels of `frame`. Recorded in the ActiveArea and
+    /// DefaultCrop tags if `area` is larger.
+    pub active_area: Area,
+}
+
+impl Image<'_> {
+    /// The packed bytes of a row of `area`.
+    fn row(&self, row: u16) -> &[u8] {
+        let first = row as usize * self.frame_width as usize + self.area.left as usize;
+        let start = first / GROUP_PIXELS * GROUP_BYTES;
+        let len = self.area.width() as usize / GROUP_PIXELS * GROUP_BYTES;
+        &self.frame[start..start + len]
+    }
+}
+
+/// Groups unpacked at a time when writing 16-bit samples.
+const UNPACK_GROUPS: usize = 64;
+
+/// Writes the samples of `image` row by row in `layout`, unpacking through
+/// a small buffer instead of a second frame buffer.
+fn write_samples<W, E>(write_all: &mut W, image: &Image, layout: SampleLayout) -> Result<(), E>
+where
+    W: FnMut(&[u8]) -> Result<(), E>,
+{
+    let shift = layout.shift();
+    let mut buffer = [0u8; UNPACK_GROUPS * GROUP_PIXELS * 2];
+    for row in image.area.top..image.area.bottom {
+        let row = image.row(row);
+        if layout == SampleLayout::Packed10 {
+            write_all(row)?;
+            continue;
+        }
+        for chunk in row.chunks(UNPACK_GROUPS * GROUP_BYTES) {
+            let samples = packed::pixels(chunk).map(|pixel| pixel << shift);
+            let mut len = 0;
+            for (bytes, sample) in buffer.chunks_exact_mut(2).zip(samples) {
+                bytes.copy_from_slice(&sample.to_le_bytes());
+                len += 2;
+            }
+            write_all(&buffer[..len])?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl DateTime {
+    /// Formats as the `YYYY:MM:DD HH:MM:SS` string TIFF and EXIF use.
+    const fn to_ascii(self) -> [u8; 20] {
+        const fn digits(value: u16) -> [u8; 2] {
+            [b'0' + (value / 10 % 10) as u8, b'0' + (value % 10) as u8]
+        }
+        let [y0, y1] = digits(self.year / 100);
+        let [y2, y3] = digits(self.year);
+        let [mo0, mo1] = digits(self.month as u16);
+        let [d0, d1] = digits(self.day as u16);
+        let [h0, h1] = digits(self.hours as u16);
+        let [mi0, mi1] = digits(self.minutes as u16);
+        let [s0, s1] = digits(self.seconds as u16);
+        [
+            y0, y1, y2, y3, b':', mo0, mo1, b':', d0, d1, b' ', h0, h1, b':', mi0, mi1, b':', s0,
+            s1, 0,
+        ]
+    }
+}
+
+/// Per-frame metadata written into the EXIF sub-IFD and the TIFF/EP tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub image_number: u32,
+    pub exposure_us: u32,
+    pub iso: u16,
+    /// Left out of the file if the camera does not know the time.
+    pub date_time: Option<DateTime>,
+    pub registers: Registers,
+    /// Row time in pixel clock periods.
+    pub row_time: u32,
+    /// Frames captured since power-up.
+    pub frame: u32,
+    /// Microseconds since power-up when the exposure was triggered.
+    pub timestamp_us: u64,
+}
+
+const fn ifd_entry(tag: u16, field_type: u16, count: u32, value_or_offset: u32) -> [u8; 12] {
+    let [t0, t1] = tag.to_le_bytes();
+    let [ft0, ft1] = field_type.to_le_bytes();
+    let [c0, c1, c2, c3] = count.to_le_bytes();
+    let [v0, v1, v2, v3] = value_or_offset.to_le_bytes();
+    [t0, t1, ft0, ft1, c0, c1, c2, c3, v0, v1, v2, v3]
+}
+
+const fn byte_entry(tag: u16, value: [u8; 4]) -> [u8; 12] {
+    ifd_entry(tag, 1, 4, u32::from_le_bytes(value))
+}
+const fn ascii_entry(tag: u16, len: u32, offset: u32) -> [u8; 12] {
+    ifd_entry(tag, 2, len, offset)
+}
+const fn short_entry(tag: u16, value: u16) -> [u8; 12] {
+    ifd_entry(tag, 3, 1, value as u32)
+}
+const fn shorts_entry(tag: u16, count: u32, offset: u32) -> [u8; 12] {
+    ifd_entry(tag, 3, count, offset)
+}
+const fn short_pair_entry(tag: u16, values: [u16; 2]) -> [u8; 12] {
+    let [a0, a1] = values[0].to_le_bytes();
+    let [b0, b1] = values[1].to_le_bytes();
+    ifd_entry(tag, 3, 2, u32::from_le_bytes([a0, a1, b0, b1]))
+}
+const fn long_entry(tag: u16, count: u32, offset: u32) -> [u8; 12] {
+    ifd_entry(tag, 4, count, offset)
+}
+const fn rational_entry(tag: u16, offset: u32) -> [u8; 12] {
+    ifd_entry(tag, 5, 1, offset)
+}
+const fn undefined_entry(tag: u16, value: [u8; 4]) -> [u8; 12] {
+    ifd_entry(tag, 7, 4, u32::from_le_bytes(value))
+}
+
+/// DNG specific image properties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dng<'a> {
+    /// Black level in 10-bit pixel values.
+    pub black_level: u16,
+    /// Maps stored sample values to linear ones, if the sensor response needs
+    /// correcting. The table is indexed by 10-bit values, so it is left out
+    /// of files with left justified samples, which would need 65536 entries.
+    pub linearization_table: Option<&'a [u16]>,
+}
+
+impl Dng<'_> {
+    /// The linearization table written along samples in `layout`.
+    fn linearization_table(&self, layout: SampleLayout) -> Option<&[u16]> {
+        self.linearization_table.filter(|_| layout.shift() == 0)
+    }
+}
+
+/// The container format of a saved frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat<'a> {
+    Tiff,
+    Dng(Dng<'a>),
+}
+
+const MAKE: &[u8] = b"Meerkat\0";
+const MODEL: &[u8] = b"Monochrome 1\0";
+const UNIQUE_CAMERA_MODEL: &[u8] = b"Meerkat Monochrome 1\0";
+const SOFTWARE: &[u8] = env!("CARGO_PKG_VERSION").as_bytes();
+const HEADER: u32 = 8; // 4-byte identifier + 4-byte IFD offset
+
+const DNG_VERSION: [u8; 4] = [1, 4, 0, 0];
+/// LinearRaw needs DNG 1.1 readers.
+const DNG_BACKWARD_VERSION: [u8; 4] = [1, 1, 0, 0];
+
+const PHOTOMETRIC_BLACK_IS_ZERO: u16 = 1;
+const PHOTOMETRIC_LINEAR_RAW: u16 = 34892;
+
+const MAX_ENTRIES: usize = 32;
+
+struct Ifd {
+    entries: [[u8; 12]; MAX_ENTRIES],
+    len: usize,
+}
+
+impl Ifd {
+    const fn new() -> Self {
+        Self {
+            entries: [[0; 12]; MAX_ENTRIES],
+            len: 0,
+        }
+    }
+
+    /// Entries have to be pushed in ascending tag order.
+    fn push(&mut self, entry: [u8; 12]) {
+        self.entries[self.len] = entry;
+        self.len += 1;
+    }
+
+    fn write<W, E>(&self, write_all: &mut W) -> Result<(), E>
+    where
+        W: FnMut(&[u8]) -> Result<(), E>,
+    {
+        write_all(&(self.len as u16).to_le_bytes())?;
+        for entry in &self.entries[..self.len] {
+            write_all(entry)?;
+        }
+        write_all(&0u32.to_le_bytes())
+    }
+}
+
+/// Size of an IFD with `entries` entries: count, entries and next IFD offset.
+const fn ifd_len(entries: usize) -> u32 {
+    2 + entries as u32 * 12 + 4
+}
+
+/// Hands out file offsets for the values following the header, keeping
+/// every value on a word boundary as TIFF requires.
+struct Offsets {
+    next: u32,
+}
+
+impl Offsets {
+    fn place(&mut self, len: u32) -> u32 {
+        let offset = self.next;
+        self.next += len + len % 2;
+        offset
+    }
+}
+
+fn write_padded<W, E>(write_all: &mut W, bytes: &[u8]) -> Result<(), E>
+where
+    W: FnMut(&[u8]) -> Result<(), E>,
+{
+    write_all(bytes)?;
+    if bytes.len() % 2 == 1 {
+        write_all(&[0])?;
+    }
+    Ok(())
+}
+
+pub fn write_single_directory_monochrome_tiff<W, E>(
+    write_all: W,
+    image: &Image,
+    layout: SampleLayout,
+    metadata: &Metadata,
+) -> Result<(), E>
+where
+    W: FnMut(&[u8]) -> Result<(), E>,
+{
+    write_monochrome(write_all, image, layout, metadata, None)
+}
+
+/// Writes a monochrome DNG. Like the TIFF writer, it only needs the
+/// streaming callback and no buffer for the whole file.
+pub fn write_monochrome_dng<W, E>(
+    write_all: W,
+    image: &Image,
+    layout: SampleLayout,
+    metadata: &Metadata,
+    dng: &Dng,
+) -> Result<(), E>
+where
+    W: FnMut(&[u8]) -> Result<(), E>,
+{
+    write_monochrome(write_all, image, layout, metadata, Some(dng))
+}
+
+fn write_monochrome<W, E>(
+    mut write_all: W,
+    image: &Image,
+    layout: SampleLayout,
+    metadata: &Metadata,
+    dng: Option<&Dng>,
+) -> Result<(), E>
+where
+    W: FnMut(&[u8]) -> Result<(), E>,
+{
+    let image_len = layout.strip_byte_count(&image.area);
+    let active_area = image.active_area.within(&image.area);
+    let date_time = metadata.date_time.map(DateTime::to_ascii);
+    let exposure_time = [metadata.exposure_us, 1_000_000];
+    let registers = metadata.registers.to_le_bytes();
+    let capture = [
+        metadata.row_time,
+        metadata.frame,
+        metadata.timestamp_us as u32,
+        (metadata.timestamp_us >> 32) as u32,
+    ];
+
+    // Values are placed in the order they are written below.
+    let mut offsets = Offsets { next: HEADER };
+    let image_offset = offsets.place(image_len);
+    let make_offset = offsets.place(MAKE.len() as u32);
+    let model_offset = offsets.place(MODEL.len() as u32);
+    let software_offset = offsets.place(SOFTWARE.len() as u32);
+    let date_time_offset = date_time.map(|date_time| offsets.place(date_time.len() as u32));
+    let exposure_time_offset = offsets.place(8);
+
+    let mut exif = Ifd::new();
+    exif.push(rational_entry(TAG_EXPOSURE_TIME, exposure_time_offset));
+    exif.push(short_entry(TAG_ISO_SPEED_RATINGS, metadata.iso));
+    exif.push(undefined_entry(TAG_EXIF_VERSION, *b"0230"));
+    if let (Some(date_time), Some(offset)) = (date_time, date_time_offset) {
+        exif.push(ascii_entry(
+            TAG_DATE_TIME_ORIGINAL,
+            date_time.len() as u32,
+            offset,
+        ));
+    }
+    let exif_offset = offsets.place(ifd_len(exif.len));
+
+    let photometric_interpretation = match dng {
+        Some(_) => PHOTOMETRIC_LINEAR_RAW,
+        None => PHOTOMETRIC_BLACK_IS_ZERO,
+    };
+    let mut ifd = Ifd::new();
+    ifd.push(short_entry(TAG_NEW_SUBFILE_TYPE, 0));
+    ifd.push(short_entry(TAG_IMAGE_WIDTH, image.area.width()));
+    ifd.push(short_entry(TAG_IMAGE_HEIGHT, image.area.height()));
+    ifd.push(short_entry(TAG_BITS_PER_SAMPLE, layout.bits_per_sample()));
+    ifd.push(short_entry(TAG_COMPRESSION, 1));
+    ifd.push(short_entry(
+        TAG_PHOTOMETRIC_INTERPRETATION,
+        photometric_interpretation,
+    ));
+    ifd.push(short_entry(TAG_FILL_ORDER, 1));
+    ifd.push(ascii_entry(TAG_MAKE, MAKE.len() as u32, make_offset));
+    ifd.push(ascii_entry(TAG_MODEL, MODEL.len() as u32, model_offset));
+    ifd.push(long_entry(TAG_STRIP_OFFSETS, 1, image_offset));
+    ifd.push(short_entry(TAG_ORIENTATION, 4));
+    ifd.push(short_entry(TAG_SAMPLES_PER_PIXEL, 1));
+    ifd.push(long_entry(TAG_STRIP_BYTE_COUNT, 1, image_len));
+    ifd.push(ascii_entry(
+        TAG_SOFTWARE,
+        SOFTWARE.len() as u32,
+        software_offset,
+    ));
+    if let (Some(date_time), Some(offset)) = (date_time, date_time_offset) {
+        ifd.push(ascii_entry(TAG_DATE_TIME, date_time.len() as u32, offset));
+    }
+    ifd.push(short_entry(TAG_SAMPLE_FORMAT, 1));
+    ifd.push(long_entry(TAG_EXIF_IFD, 1, exif_offset));
+    ifd.push(long_entry(TAG_IMAGE_NUMBER, 1, metadata.image_number));
+    if let Some(dng) = dng {
+        let shift = layout.shift();
+        ifd.push(byte_entry(TAG_DNG_VERSION, DNG_VERSION));
+        ifd.push(byte_entry(TAG_DNG_BACKWARD_VERSION, DNG_BACKWARD_VERSION));
+        ifd.push(ascii_entry(
+            TAG_UNIQUE_CAMERA_MODEL,
+            UNIQUE_CAMERA_MODEL.len() as u32,
+            offsets.place(UNIQUE_CAMERA_MODEL.len() as u32),
+        ));
+        if let Some(table) = dng.linearization_table(layout) {
+            ifd.push(shorts_entry(
+                TAG_LINEARIZATION_TABLE,
+                table.len() as u32,
+                offsets.place(table.len() as u32 * 2),
+            ));
+        }
+        ifd.push(long_entry(
+            TAG_BLACK_LEVEL,
+            1,
+            (dng.black_level as u32) << shift,
+        ));
+        ifd.push(long_entry(
+            TAG_WHITE_LEVEL,
+            1,
+            (WHITE_LEVEL as u32) << shift,
+        ));
+    }
+    let cropped = image.active_area != image.area;
+    if cropped {
+        ifd.push(short_pair_entry(TAG_DEFAULT_CROP_ORIGIN, [0, 0]));
+        ifd.push(short_pair_entry(
+            TAG_DEFAULT_CROP_SIZE,
+            [active_area.width(), active_area.height()],
+        ));
+        ifd.push(shorts_entry(TAG_ACTIVE_AREA, 4, offsets.place(8)));
+    }
+    ifd.push(shorts_entry(
+        TAG_SENSOR_REGISTERS,
+        Registers::COUNT as u32,
+        offsets.place(registers.len() as u32),
+    ));
+    ifd.push(long_entry(
+        TAG_SENSOR_CAPTURE,
+        capture.len() as u32,
+        offsets.place(capture.len() as u32 * 4),
+    ));
+    let ifd_offset = offsets.place(ifd_len(ifd.len));
+
+    write_all(&[b'I', b'I', 0x2A, 0x00])?;
+    write_all(&ifd_offset.to_le_bytes())?;
+
+    write_samples(&mut write_all, image, layout)?;
+    if image_len % 2 == 1 {
+        write_all(&[0])?;
+    }
+    write_padded(&mut write_all, MAKE)?;
+    write_padded(&mut write_all, MODEL)?;
+    write_padded(&mut write_all, SOFTWARE)?;
+    if let Some(date_time) = date_time {
+        write_padded(&mut write_all, &date_time)?;
+    }
+    for value in exposure_time {
+        write_all(&value.to_le_bytes())?;
+    }
+    exif.write(&mut write_all)?;
+    if let Some(dng) = dng {
+        write_padded(&mut write_all, UNIQUE_CAMERA_MODEL)?;
+        for value in dng.linearization_table(layout).unwrap_or_default() {
+            write_all(&value.to_le_bytes())?;
+        }
+    }
+    if cropped {
+        let Area {
+            top,
+            left,
+            bottom,
+            right,
+        } = active_area;
+        for value in [top, left, bottom, right] {
+            write_all(&value.to_le_bytes())?;
+        }
+    }
+    write_all(&registers)?;
+    for value in capture {
+        write_all(&value.to_le_bytes())?;
+    }
+    ifd.write(&mut write_all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{ACTIVE, Framing, READOUT};
+    use std::vec::Vec;
+
+    const AREA: Area = Area {
+        top: 0,
+        left: 0,
+        bottom: 2,
+        right: 8,
+    };
+
+    fn metadata(date_time: Option<DateTime>) -> Metadata {
+        Metadata {
+            image_number: 42,
+            exposure_us: 10_000,
+            iso: 100,
+            date_time,
+            registers: Registers {
+                shutter_width: 42,
+                global_gain: 8,
+                column_start: 0,
+                row_start: 0,
+                column_size: 7,
+                row_size: 1,
+                analog_offsets: [0; 4],
+            },
+            row_time: 252,
+            frame: 1,
+            timestamp_us: 1 << 32,
+        }
+    }
+
+    fn write(frame: &[u8], layout: SampleLayout, metadata: &Metadata) -> Vec<u8> {
+        let image = Image {
+            frame,
+            frame_width: AREA.width(),
+            area: AREA,
+            active_area: AREA,
+        };
+        let mut file = Vec::new();
+        write_single_directory_monochrome_tiff(
+            |bytes: &[u8]| -> Result<(), ()> {
+                file.extend_from_slice(bytes);
+                Ok(())
+            },
+            &image,
+            layout,
+            metadata,
+        )
+        .unwrap();
+        file
+    }
+
+    fn u16_at(file: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([file[offset], file[offset + 1]])
+    }
+
+    fn u32_at(file: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Returns the tag, type, count and value or offset of the entries of
+    /// the IFD at `offset`.
+    fn entries(file: &[u8], offset: u32) -> Vec<(u16, u16, u32, u32)> {
+        let offset = offset as usize;
+        (0..u16_at(file, offset) as usize)
+            .map(|i| offset + 2 + i * 12)
+            .map(|entry| {
+                (
+                    u16_at(file, entry),
+                    u16_at(file, entry + 2),
+                    u32_at(file, entry + 4),
+                    u32_at(file, entry + 8),
+                )
+            })
+            .collect()
+    }
+
+    fn typed_entry(entries: &[(u16, u16, u32, u32)], tag: u16) -> Option<(u16, u32, u32)> {
+        entries
+            .iter()
+            .find(|(t, _, _, _)| *t == tag)
+            .map(|(_, field_type, count, value)| (*field_type, *count, *value))
+    }
+
+    fn entry(entries: &[(u16, u16, u32, u32)], tag: u16) -> Option<(u32, u32)> {
+        typed_entry(entries, tag).map(|(_, count, value)| (count, value))
+    }
+
+    fn write_dng(image: &Image, layout: SampleLayout, dng: &Dng) -> Vec<u8> {
+        let mut file = Vec::new();
+        write_monochrome_dng(
+            |bytes: &[u8]| -> Result<(), ()> {
+                file.extend_from_slice(bytes);
+                Ok(())
+            },
+            image,
+            layout,
+            &metadata(None),
+            dng,
+        )
+        .unwrap();
+        file
+    }
+
+    /// A blank readout saved as a DNG with `framing`.
+    fn write_framed(framing: Framing) -> Vec<u8> {
+        let pixels = READOUT.width() as usize * READOUT.height() as usize;
+        let frame = std::vec![0; pixels / GROUP_PIXELS * GROUP_BYTES];
+        let image = Image {
+            frame: &frame,
+            frame_width: READOUT.width(),
+            area: framing.saved_area(),
+            active_area: ACTIVE,
+        };
+        let dng = Dng {
+            black_level: 0,
+            linearization_table: None,
+        };
+        write_dng(&image, SampleLayout::Packed10, &dng)
+    }
+
+    /// The 10-bit samples of `file`, which is `AREA` written as `layout`.
+    fn samples(file: &[u8], layout: SampleLayout) -> Vec<u16> {
+        let ifd = entries(file, u32_at(file, 4));
+        let (_, offset) = entry(&ifd, TAG_STRIP_OFFSETS).unwrap();
+        let (_, len) = entry(&ifd, TAG_STRIP_BYTE_COUNT).unwrap();
+        let strip = &file[offset as usize..(offset + len) as usize];
+        match layout {
+            SampleLayout::Packed10 => packed::pixels(strip).collect(),
+            SampleLayout::Unpacked16(_) => strip
+                .chunks_exact(2)
+                .map(|sample| u16::from_le_bytes([sample[0], sample[1]]) >> layout.shift())
+                .collect(),
+        }
+    }
+
+    /// Runs the `main.pio` program on `pixels`. The sensor's DOUT9 is wired
+    /// to the lowest input pin, so the pins read each pixel bit reversed.
+    fn shift_in_like_pio(pixels: &[u16]) -> Vec<u8> {
+        struct Pio {
+            isr: u32,
+            count: u32,
+            osr: u32,
+            x: u32,
+            y: u32,
+            rx: Vec<u8>,
+        }
+
+        impl Pio {
+            /// `mov osr, ::pins` then `out null, 22`.
+            fn read_pins(&mut self, pixel: u16) {
+                let pins = (pixel as u32).reverse_bits() >> 22;
+                self.osr = pins.reverse_bits() >> 22;
+            }
+
+            fn out(&mut self, bits: u32) -> u32 {
+                let value = self.osr & ((1 << bits) - 1);
+                self.osr >>= bits;
+                value
+            }
+
+            /// `in` shifting right, with autopush at 32 bits into a DMA
+            /// which stores the words little endian.
+            fn shift_in(&mut self, value: u32, bits: u32) {
+                self.isr = (self.isr >> bits) | ((value & ((1 << bits) - 1)) << (32 - bits));
+                self.count += bits;
+                if self.count == 32 {
+                    self.rx.extend_from_slice(&self.isr.to_le_bytes());
+                    self.count = 0;
+                }
+            }
+        }
+
+        let mut pio = Pio {
+            isr: 0,
+            count: 0,
+            osr: 0,
+            x: 0,
+            y: 0,
+            rx: Vec::new(),
+        };
+        for group in pixels.chunks_exact(GROUP_PIXELS) {
+            pio.read_pins(group[0]);
+            pio.x = pio.out(2);
+            pio.shift_in(pio.osr, 8);
+
+            pio.read_pins(group[1]);
+            pio.y = pio.out(4);
+            pio.shift_in(pio.osr, 6);
+            pio.shift_in(pio.x, 2);
+
+            pio.read_pins(group[2]);
+            pio.x = pio.out(6);
+            pio.shift_in(pio.osr, 4);
+            pio.shift_in(pio.y, 4);
+
+            pio.read_pins(group[3]);
+            pio.y = pio.out(8);
+            pio.shift_in(pio.osr, 2);
+            pio.shift_in(pio.x, 6);
+            pio.shift_in(pio.y, 8);
+        }
+        assert_eq!(pio.count, 0);
+        pio.rx
+    }
+
+    #[test]
+    fn pio_stream_is_packed_layout() {
+        // 16 pixels fill whole words, like every frame the sensor reads out.
+        let pixels = [
+            0x3FF, 0x000, 0x155, 0x2AA, 0x201, 0x102, 0x0F0, 0x30F, 0x001, 0x200, 0x3FE, 0x07F,
+            0x123, 0x234, 0x345, 0x056,
+        ];
+        let frame = shift_in_like_pio(&pixels);
+        assert_eq!(&frame[..5], packed::pack([0x3FF, 0x000, 0x155, 0x2AA]));
+        assert_eq!(&frame[5..10], packed::pack([0x201, 0x102, 0x0F0, 0x30F]));
+        assert_eq!(packed::pixels(&frame).collect::<Vec<_>>(), pixels);
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        let pixels: Vec<u16> = (0..AREA.width() * AREA.height())
+            .map(|i| i * 67 % 1024)
+            .collect();
+        let frame = shift_in_like_pio(&pixels);
+        for layout in [
+            SampleLayout::Packed10,
+            SampleLayout::Unpacked16(Justification::Left),
+            SampleLayout::Unpacked16(Justification::Right),
+        ] {
+            let file = write(&frame, layout, &metadata(None));
+            assert_eq!(samples(&file, layout), pixels, "{layout:?}");
+        }
+    }
+
+    #[test]
+    fn dng_black_level_is_scaled_like_the_samples() {
+        let image = Image {
+            frame: &[0; 20],
+            frame_width: AREA.width(),
+            area: AREA,
+            active_area: AREA,
+        };
+        let dng = Dng {
+            black_level: 42,
+            linearization_table: None,
+        };
+        for (layout, black_level) in [
+            (SampleLayout::Packed10, 42),
+            (SampleLayout::Unpacked16(Justification::Right), 42),
+            (SampleLayout::Unpacked16(Justification::Left), 42 << 6),
+        ] {
+            let file = write_dng(&image, layout, &dng);
+            let ifd = entries(&file, u32_at(&file, 4));
+            assert_eq!(entry(&ifd, TAG_BLACK_LEVEL), Some((1, black_level)));
+        }
+    }
+
+    #[test]
+    fn date_time_is_written() {
+        let date_time = DateTime {
+            year: 2026,
+            month: 10,
+            day: 18,
+            hours: 9,
+            minutes: 5,
+            seconds: 0,
+        };
+        let file = write(&[0; 20], SampleLayout::Packed10, &metadata(Some(date_time)));
+        let ifd = entries(&file, u32_at(&file, 4));
+        let (count, offset) = entry(&ifd, TAG_DATE_TIME).unwrap();
+        assert_eq!(
+            &file[offset as usize..(offset + count) as usize],
+            b"2026:10:18 09:05:00\0"
+        );
+        let (_, exif) = entry(&ifd, TAG_EXIF_IFD).unwrap();
+        let exif = entries(&file, exif);
+        assert_eq!(entry(&exif, TAG_DATE_TIME_ORIGINAL), Some((count, offset)));
+    }
+
+    #[test]
+    fn unknown_date_time_is_left_out() {
+        let frame: Vec<u8> = (0..20).collect();
+        let file = write(&frame, SampleLayout::Packed10, &metadata(None));
+        let ifd = entries(&file, u32_at(&file, 4));
+        assert_eq!(entry(&ifd, TAG_DATE_TIME), None);
+        let (_, exif) = entry(&ifd, TAG_EXIF_IFD).unwrap();
+        assert_eq!(entry(&entries(&file, exif), TAG_DATE_TIME_ORIGINAL), None);
+        assert_eq!(entry(&ifd, TAG_IMAGE_NUMBER), Some((1, 42)));
+        assert_eq!(
+            samples(&file, SampleLayout::Packed10),
+            packed::pixels(&frame).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn dng_tags_are_written() {
+        const TABLE: [u16; 4] = [0, 300, 600, 1023];
+        let image = Image {
+            frame: &[0; 20],
+            frame_width: AREA.width(),
+            area: AREA,
+            active_area: AREA,
+        };
+        let dng = Dng {
+            black_level: 42,
+            linearization_table: Some(&TABLE),
+        };
+        let file = write_dng(&image, SampleLayout::Packed10, &dng);
+        let ifd = entries(&file, u32_at(&file, 4));
+        let bytes = |(_, count, offset): (u16, u32, u32)| {
+            file[offset as usize..(offset + count) as usize].to_vec()
+        };
+
+        assert_eq!(
+            typed_entry(&ifd, TAG_PHOTOMETRIC_INTERPRETATION),
+            Some((3, 1, PHOTOMETRIC_LINEAR_RAW as u32))
+        );
+        assert_eq!(
+            typed_entry(&ifd, TAG_DNG_VERSION),
+            Some((1, 4, u32::from_le_bytes([1, 4, 0, 0])))
+        );
+        assert_eq!(
+            typed_entry(&ifd, TAG_DNG_BACKWARD_VERSION),
+            Some((1, 4, u32::from_le_bytes([1, 1, 0, 0])))
+        );
+        let model = typed_entry(&ifd, TAG_UNIQUE_CAMERA_MODEL).unwrap();
+        assert_eq!((model.0, model.1), (2, 21));
+        assert_eq!(bytes(model), b"Meerkat Monochrome 1\0");
+        let (field_type, count, offset) = typed_entry(&ifd, TAG_LINEARIZATION_TABLE).unwrap();
+        assert_eq!((field_type, count), (3, 4));
+        let table: Vec<u16> = (0..count)
+            .map(|i| u16_at(&file, (offset + i * 2) as usize))
+            .collect();
+        assert_eq!(table, TABLE);
+        assert_eq!(typed_entry(&ifd, TAG_BLACK_LEVEL), Some((4, 1, 42)));
+        assert_eq!(typed_entry(&ifd, TAG_WHITE_LEVEL), Some((4, 1, 1023)));
+
+        let file = write(&[0; 20], SampleLayout::Packed10, &metadata(None));
+        let ifd = entries(&file, u32_at(&file, 4));
+        for tag in [
+            TAG_DNG_VERSION,
+            TAG_UNIQUE_CAMERA_MODEL,
+            TAG_BLACK_LEVEL,
+            TAG_WHITE_LEVEL,
+        ] {
+            assert_eq!(entry(&ifd, tag), None);
+        }
+    }
+
+    #[test]
+    fn linearization_table_is_left_out_of_left_justified_samples() {
+        let image = Image {
+            frame: &[0; 20],
+            frame_width: AREA.width(),
+            area: AREA,
+            active_area: AREA,
+        };
+        let dng = Dng {
+            black_level: 0,
+            linearization_table: Some(&[0, 1023]),
+        };
+        for (layout, written) in [
+            (SampleLayout::Packed10, true),
+            (SampleLayout::Unpacked16(Justification::Right), true),
+            (SampleLayout::Unpacked16(Justification::Left), false),
+        ] {
+            let file = write_dng(&image, layout, &dng);
+            let ifd = entries(&file, u32_at(&file, 4));
+            let table = entry(&ifd, TAG_LINEARIZATION_TABLE);
+            assert_eq!(table.is_some(), written, "{layout:?}");
+            let (_, white_level) = entry(&ifd, TAG_WHITE_LEVEL).unwrap();
+            assert_eq!(white_level, 1023 << layout.shift());
+        }
+    }
+
+    #[test]
+    fn cropped_frames_hold_the_active_area() {
+        let file = write_framed(Framing::Crop);
+        let ifd = entries(&file, u32_at(&file, 4));
+        assert_eq!(
+            entry(&ifd, TAG_IMAGE_WIDTH),
+            Some((1, ACTIVE.width() as u32))
+        );
+        assert_eq!(
+            entry(&ifd, TAG_IMAGE_HEIGHT),
+            Some((1, ACTIVE.height() as u32))
+        );
+        for tag in [
+            TAG_ACTIVE_AREA,
+            TAG_DEFAULT_CROP_ORIGIN,
+            TAG_DEFAULT_CROP_SIZE,
+        ] {
+            assert_eq!(entry(&ifd, tag), None);
+        }
+    }
+
+    #[test]
+    fn whole_readouts_record_the_active_area() {
+        let file = write_framed(Framing::Keep);
+        let ifd = entries(&file, u32_at(&file, 4));
+        assert_eq!(
+            entry(&ifd, TAG_IMAGE_WIDTH),
+            Some((1, READOUT.width() as u32))
+        );
+        assert_eq!(
+            entry(&ifd, TAG_IMAGE_HEIGHT),
+            Some((1, READOUT.height() as u32))
+        );
+
+        let (field_type, count, offset) = typed_entry(&ifd, TAG_ACTIVE_AREA).unwrap();
+        assert_eq!((field_type, count), (3, 4));
+        let edges: Vec<u16> = (0..4)
+            .map(|i| u16_at(&file, (offset + i * 2) as usize))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                ACTIVE.top - READOUT.top,
+                ACTIVE.left - READOUT.left,
+                ACTIVE.bottom - READOUT.top,
+                ACTIVE.right - READOUT.left,
+            ]
+        );
+        // The default crop is relative to the active area.
+        assert_eq!(typed_entry(&ifd, TAG_DEFAULT_CROP_ORIGIN), Some((3, 2, 0)));
+        assert_eq!(
+            typed_entry(&ifd, TAG_DEFAULT_CROP_SIZE),
+            Some((3, 2, ACTIVE.width() as u32 | (ACTIVE.height() as u32) << 16))
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! The RP2350 side of the `camera_core::platform` traits.
+
+use camera_core::{
+    controls::{DialInputs, DialPositions},
+    platform::{self, ClockControl, FrameSource},
+};
+use embedded_hal::delay::DelayNs;
+use pio::{Instruction, InstructionOperands, JmpCondition};
+use rp235x_hal::{
+    Timer,
+    adc::{Adc, AdcPin},
+    clocks::{GpioOutput0Clock, StoppableClock},
+    dma::{CH1, Channel, single_buffer},
+    gpio::AnyPin,
+    pac::PIO0,
+    pio::{Running, Rx, SM0, StateMachine, Stopped},
+    timer::CopyableTimer0,
+};
+
+/// The clock output the sensor runs on.
+pub struct SensorClock(pub GpioOutput0Clock);
+
+impl ClockControl for SensorClock {
+    fn enable(&mut self) {
+        self.0.enable();
+    }
+
+    fn disable(&mut self) {
+        self.0.disable();
+    }
+}
+
+/// The microsecond timer, which is copied to everything that waits.
+#[derive(Clone, Copy)]
+pub struct BoardTimer(pub Timer<CopyableTimer0>);
+
+impl DelayNs for BoardTimer {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0.delay_ns(ns);
+    }
+}
+
+impl platform::Timer for BoardTimer {
+    fn now_us(&mut self) -> u64 {
+        self.0.get_counter().ticks()
+    }
+}
+
+enum PioState {
+    Idle(
+        StateMachine<(PIO0, SM0), Stopped>,
+        Channel<CH1>,
+        Rx<(PIO0, SM0)>,
+        &'static mut [u32],
+    ),
+    /// Receiving into the start of a buffer of the given length.
+    Receiving(
+        StateMachine<(PIO0, SM0), Running>,
+        single_buffer::Transfer<Channel<CH1>, Rx<(PIO0, SM0)>, &'static mut [u32]>,
+        usize,
+    ),
+    /// Only while moving between the other states.
+    Moving,
+}
+
+#[derive(Debug)]
+pub enum PioFrameSourceError {
+    /// A frame was started while another one is being received.
+    Busy,
+    /// A frame was waited for without being started.
+    NotStarted,
+    /// The frame does not fit into the frame buffer.
+    TooLarge,
+}
+
+/// Frames shifted in by the `main.pio` program and moved into the PSRAM
+/// frame buffer by DMA.
+pub struct PioFrameSource {
+    state: PioState,
+    program_offset: u8,
+}
+
+impl PioFrameSource {
+    /// `program_offset` is where the `main.pio` program is installed, which
+    /// every frame starts from.
+    pub fn new(
+        sm: StateMachine<(PIO0, SM0), Stopped>,
+        program_offset: u8,
+        channel: Channel<CH1>,
+        rx: Rx<(PIO0, SM0)>,
+        buffer: &'static mut [u32],
+    ) -> Self {
+        Self {
+            state: PioState::Idle(sm, channel, rx, buffer),
+            program_offset,
+        }
+    }
+}
+
+impl FrameSource for PioFrameSource {
+    type Error = PioFrameSourceError;
+
+    fn start(&mut self, words: usize) -> Result<(), PioFrameSourceError> {
+        let PioState::Idle(mut sm, channel, rx, buffer) =
+            core::mem::replace(&mut self.state, PioState::Moving)
+        else {
+            return Err(PioFrameSourceError::Busy);
+        };
+        let length = buffer.len();
+        if words > length {
+            self.state = PioState::Idle(sm, channel, rx, buffer);
+            return Err(PioFrameSourceError::TooLarge);
+        }
+        let (head, _) = <[u32]>::split_at_mut(buffer, words);
+
+        // A frame that was cut short leaves pixels in the ISR and the program
+        // somewhere in the middle of a group, which would shift every pixel of
+        // the next frame.
+        sm.clear_fifos();
+        sm.restart();
+        sm.exec_instruction(Instruction {
+            operands: InstructionOperands::JMP {
+                condition: JmpCondition::Always,
+                address: self.program_offset,
+            },
+            delay: 0,
+            side_set: None,
+        });
+        let running_sm = sm.start();
+        let mut transfer = single_buffer::Config::new(channel, rx, head);
+        transfer.bswap(false);
+        self.state = PioState::Receiving(running_sm, transfer.start(), length);
+        Ok(())
+    }
+
+    fn wait(&mut self) -> Result<(), PioFrameSourceError> {
+        let PioState::Receiving(running_sm, transfer, length) =
+            core::mem::replace(&mut self.state, PioState::Moving)
+        else {
+            return Err(PioFrameSourceError::NotStarted);
+        };
+        let (channel, rx, head) = transfer.wait();
+        let sm = running_sm.stop();
+
+        // SAFETY: `head` starts the buffer split by `start`, and the rest of
+        // it was never handed out.
+        let buffer = unsafe { core::slice::from_raw_parts_mut(head.as_mut_ptr(), length) };
+        self.state = PioState::Idle(sm, channel, rx, buffer);
+        Ok(())
+    }
+
+    fn frame(&self) -> &[u32] {
+        match &self.state {
+            PioState::Idle(_, _, _, buffer) => buffer,
+            _ => &[],
+        }
+    }
+
+    fn frame_mut(&mut self) -> &mut [u32] {
+        match &mut self.state {
+            PioState::Idle(_, _, _, buffer) => buffer,
+            _ => &mut [],
+        }
+    }
+}
+
+/// SHUTTER_SPEED and GAIN potentiometers sampled by the on-chip ADC.
+pub struct AdcDials<S: AnyPin, G: AnyPin> {
+    adc: Adc,
+    shutter_speed: AdcPin<S>,
+    gain: AdcPin<G>,
+}
+
+impl<S, G> AdcDials<S, G>
+where
+    S: AnyPin,
+    G: AnyPin,
+{
+    pub fn new(adc: Adc, shutter_speed: AdcPin<S>, gain: AdcPin<G>) -> Self {
+        Self {
+            adc,
+            shutter_speed,
+            gain,
+        }
+    }
+}
+
+impl<S, G> DialInputs for AdcDials<S, G>
+where
+    S: AnyPin,
+    G: AnyPin,
+{
+    type Error = rp235x_hal::adc::Error;
+
+    fn sample(&mut self) -> Result<DialPositions, Self::Error> {
+        Ok(DialPositions {
+            shutter_speed: self.adc.read(&mut self.shutter_speed)?,
+            gain: self.adc.read(&mut self.gain)?,
+        })
+    }
+}
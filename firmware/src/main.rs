@@ -1,27 +1,68 @@
 #![no_std]
 #![no_main]
 
-mod fram;
+mod hardware;
 mod psram;
 mod sdmmc;
-mod sensor;
-mod tiff;
 
+use camera_core::{
+    camera::{self, CameraError, CameraState},
+    controls, counter, exposure, flatfield,
+    geometry::{self, Framing},
+    naming::FileNaming,
+    parity,
+    pipeline::{Pipeline, PipelineError},
+    platform::{Fram, PersistentMemory},
+    sensor,
+    settings::{CameraSettings, SettingsStore},
+    tiff::{Dng, FileFormat, SampleLayout},
+};
 use core::panic::PanicInfo;
 use embedded_hal::{delay::DelayNs, digital::OutputPin};
 use embedded_hal_bus::spi::ExclusiveDevice;
+use hardware::{BoardTimer, SensorClock};
 use rp235x_hal::{
     self as hal, Clock, Timer,
     clocks::StoppableClock,
-    dma::{DMAExt, single_buffer},
+    dma::DMAExt,
     fugit::RateExtU32,
     gpio::{self, FunctionI2C, PinState},
     pio::PIOExt,
     timer::CopyableTimer0,
 };
 
-const NUMBER_OF_PIXELS: usize = (sensor::WIDTH as usize + 2) * sensor::HEIGHT as usize;
+const NUMBER_OF_PIXELS: usize =
+    geometry::READOUT.width() as usize * geometry::READOUT.height() as usize;
 const U32_IMAGE_BUFFER_LENGTH: usize = NUMBER_OF_PIXELS * 10 / 32;
+/// Packed 10-bit samples keep files small; 16-bit samples open in more tools.
+const SAMPLE_LAYOUT: SampleLayout = SampleLayout::Packed10;
+const FILE_FORMAT: FileFormat = FileFormat::Dng(Dng {
+    // Only used until the parity calibration knows the black level, which
+    // images are then tagged with unless a dark frame was subtracted.
+    black_level: 0,
+    linearization_table: None,
+});
+/// Dark and padding pixels are only useful when calibrating the sensor.
+const FRAMING: Framing = Framing::Crop;
+/// Used until settings are stored in the F-RAM.
+const DEFAULT_SETTINGS: CameraSettings = CameraSettings {
+    exposure: controls::ExposureSettings {
+        exposure: match exposure::Exposure::from_micros(10_000) {
+            Ok(exposure) => exposure,
+            Err(_) => panic!("invalid exposure"),
+        },
+        gain: exposure::Gain::UNITY,
+    },
+    sample_layout: SAMPLE_LAYOUT,
+    file_format: FILE_FORMAT,
+    framing: FRAMING,
+    file_naming: match FileNaming::new(*b"IM") {
+        Some(file_naming) => file_naming,
+        None => panic!("invalid file name prefix"),
+    },
+    calibration: parity::ParityCalibration::NONE,
+    black_level: sensor::BlackLevelMode::Manual(parity::ParityCalibration::NONE.offsets),
+};
 
 #[unsafe(link_section = ".start_block")]
 #[used]
@@ -59,12 +100,13 @@ fn main() -> ! {
     psram::init(&p.QMI, &mut timer, clocks.system_clock.freq().to_Hz());
     // Make PSRAM writable
     p.XIP_CTRL.ctrl().modify(|_, w| w.writable_m1().set_bit());
+    // SAFETY: The 8 MiB PSRAM is mapped from `BASE_ADDRESS` and nothing
+    // else refers to it.
     let psram_base =
         unsafe { core::slice::from_raw_parts_mut(psram::BASE_ADDRESS as *mut u8, 1024 * 1024 * 8) };
 
     // FRAM
-    let mut fram_cs = pins.gpio17.into_push_pull_output();
-    fram_cs.set_high();
+    let fram_cs = pins.gpio17.into_push_pull_output();
     let fram_spi_rx = pins.gpio16.into_function::<gpio::FunctionSpi>();
     let fram_spi_sclk = pins.gpio18.into_function::<gpio::FunctionSpi>();
     let fram_spi_tx = pins.gpio19.into_function::<gpio::FunctionSpi>();
@@ -76,7 +118,9 @@ fn main() -> ! {
         24.MHz(),
         embedded_hal::spi::MODE_0,
     );
-    let mut fram = fram::FM25L16B::new(fram_cs, fram_spi);
+    let fram_spi =
+        ExclusiveDevice::new_no_delay(fram_spi, fram_cs).expect("Failed to create SPI device");
+    let mut fram = Fram::new(fm25l16b::FM25L16B::new(fram_spi), BoardTimer(timer));
 
     // Sensor
     let sensor_standby = pins.gpio4.into_push_pull_output_in_state(PinState::Low);
@@ -98,12 +142,31 @@ fn main() -> ! {
         &clocks.system_clock,
     );
     let mut sensor = sensor::Sensor::new(
-        clocks.gpio_output0_clock,
-        timer,
+        SensorClock(clocks.gpio_output0_clock),
+        BoardTimer(timer),
         sensor_i2c,
         sensor_standby,
         sensor_trigger,
     );
+    if fram.protect().is_err() {
+        blink(&mut timer, &mut status_led, 4);
+        panic!("cannot protect the F-RAM");
+    }
+    let Ok((settings_store, camera_settings)) = SettingsStore::load(&mut fram, DEFAULT_SETTINGS)
+    else {
+        blink(&mut timer, &mut status_led, 4);
+        panic!("cannot read settings");
+    };
+    let Ok(frame_counter) = counter::FrameCounter::load(&mut fram) else {
+        blink(&mut timer, &mut status_led, 4);
+        panic!("cannot read the frame counter");
+    };
+    if fram.sleep().is_err() {
+        blink(&mut timer, &mut status_led, 4);
+        panic!("cannot put the F-RAM to sleep");
+    }
+    sensor.set_parity(camera_settings.calibration);
+    sensor.set_black_level(camera_settings.black_level);
     if sensor.init().is_err() {
         blink(&mut timer, &mut status_led, 5);
         panic!("cannot initialize the sensor");
@@ -117,6 +180,7 @@ fn main() -> ! {
     );
     let (mut pio, sm0, _, _, _) = p.PIO0.split(&mut p.RESETS);
     let installed_program = pio.install(&pio_capture.program).unwrap();
+    let program_offset = installed_program.offset();
     let sensor_d0: gpio::Pin<_, gpio::FunctionPio0, _> = pins.gpio6.into_function();
     let sensor_d1: gpio::Pin<_, gpio::FunctionPio0, _> = pins.gpio7.into_function();
     let sensor_d2: gpio::Pin<_, gpio::FunctionPio0, _> = pins.gpio8.into_function();
@@ -153,10 +217,15 @@ fn main() -> ! {
     ]);
 
     // Sensor to PSRAM transfer (DMA)
+    // SAFETY: Any bytes are a valid u32. `BASE_ADDRESS` is word aligned,
+    // so nothing of the PSRAM is left in the prefix.
     let (_, u32_slice, _) = unsafe { psram_base.align_to_mut::<u32>() };
-    let image_buf: &mut [u32] = &mut u32_slice[..U32_IMAGE_BUFFER_LENGTH];
+    let (image_buf, rest) = u32_slice.split_at_mut(U32_IMAGE_BUFFER_LENGTH);
+    let (dark_frame_buf, rest) = rest.split_at_mut(U32_IMAGE_BUFFER_LENGTH);
+    // SAFETY: Any bytes are a valid u16, and u32 words are u16 aligned.
+    let (_, flat_field_buf, _) = unsafe { rest.align_to_mut::<u16>() };
+    let flat_field_buf = &mut flat_field_buf[..flatfield::HEADER_WORDS + NUMBER_OF_PIXELS];
     let dma = p.DMA.split(&mut p.RESETS);
-    let mut transfer = single_buffer::Config::new(dma.ch1, rx, image_buf);
 
     // SDMMC and file system setup
     let sdmmc_spi_rx = pins.gpio24.into_function::<hal::gpio::FunctionSpi>();
@@ -174,56 +243,73 @@ fn main() -> ! {
     let sdmmc_spi_bus = ExclusiveDevice::new_no_delay(sdmmc_spi_bus, sdmmc_spi_cs)
         .expect("Failed to create SPI device");
 
-    let mut sdmmc_memory = sdmmc::Sdmmc::new(sdmmc_spi_bus, &mut timer);
-
-    // Capture frame...
-    for denominator in (50..=51).step_by(50) {
-        sm.clear_fifos();
-        let running_sm = sm.start();
-        transfer.bswap(false);
-        let running_transfer = transfer.start();
-
-        status_led.set_high().unwrap();
-        let capture_result = sensor.configure_and_capture(1f32, (1, denominator), || {
-            let (channel, from, to) = running_transfer.wait();
-            let stopped_sm = running_sm.stop();
-            (stopped_sm, (channel, from, to))
-        });
-        status_led.set_low().unwrap();
-
-        (sm, transfer) = if let Ok((returned_sm, (channel, from, to))) = capture_result {
-            let image_counter = if let Ok(v) = fram.read(0).and_then(|v: u64| {
-                fram.write(0, v.wrapping_add(1))?;
-                Ok(v)
-            }) {
-                v
-            } else {
-                blink(&mut timer, &mut status_led, 4);
-                panic!("cannot read or incrament image counter");
-            };
-            let image_counter = (image_counter % u16::MAX as u64) as u16;
-
-            let (_, image, _) = unsafe { to.align_to_mut::<u8>() };
-            if sdmmc_memory
-                .write_image(image_counter, sensor::WIDTH + 2, sensor::HEIGHT, 10, image)
-                .is_err()
-            {
-                blink(&mut timer, &mut status_led, 6);
-                panic!("cannot save image");
-            }
+    let mut sdmmc_timer = timer;
+    let sdmmc_memory = sdmmc::Sdmmc::new(sdmmc_spi_bus, &mut sdmmc_timer);
+
+    // Exposure dials
+    let adc = hal::adc::Adc::new(p.ADC, &mut p.RESETS);
+    let shutter_speed_dial = hal::adc::AdcPin::new(pins.gpio28.into_floating_input()).unwrap();
+    let gain_dial = hal::adc::AdcPin::new(pins.gpio29.into_floating_input()).unwrap();
+    let dials = hardware::AdcDials::new(adc, shutter_speed_dial, gain_dial);
+    let frame_source = hardware::PioFrameSource::new(sm, program_offset, dma.ch1, rx, image_buf);
+
+    // Camera
+    let shutter_button = pins.gpio23.into_pull_up_input();
+    let mut camera =
+        camera::Camera::new(shutter_button, camera::DEBOUNCE_MS, camera::LONG_PRESS_MS);
+    let mut pipeline = Pipeline::new(
+        sensor,
+        fram,
+        sdmmc_memory,
+        status_led,
+        dials,
+        frame_source,
+        dark_frame_buf,
+        flat_field_buf,
+        camera_settings,
+        settings_store,
+        frame_counter,
+    );
 
-            (returned_sm, single_buffer::Config::new(channel, from, to))
-        } else {
-            blink(&mut timer, &mut status_led, 7);
-            panic!("cannot capture frame");
-        };
+    if pipeline.load_flat_field().is_err() {
+        blink(&mut timer, &mut pipeline.status_led, 10);
+        panic!("cannot load flat field");
     }
 
     loop {
-        let _ = status_led.set_high();
-        timer.delay_ms(50);
-        let _ = status_led.set_low();
-        timer.delay_ms(50);
+        let now_ms = (timer.get_counter().ticks() / 1_000) as u32;
+        match camera.poll(&mut pipeline, now_ms) {
+            Ok(CameraState::Idle | CameraState::Held) => timer.delay_ms(1),
+            Ok(_) => {}
+            Err(CameraError::ShutterButton) => {
+                blink(&mut timer, &mut pipeline.status_led, 8);
+                panic!("cannot read shutter button");
+            }
+            Err(CameraError::Backend(PipelineError::Controls)) => {
+                blink(&mut timer, &mut pipeline.status_led, 9);
+                panic!("cannot read exposure dials");
+            }
+            Err(CameraError::Backend(PipelineError::Capture)) => {
+                blink(&mut timer, &mut pipeline.status_led, 7);
+                panic!("cannot capture frame");
+            }
+            Err(CameraError::Backend(PipelineError::FrameCounter)) => {
+                blink(&mut timer, &mut pipeline.status_led, 4);
+                panic!("cannot read or incrament image counter");
+            }
+            Err(CameraError::Backend(PipelineError::Save)) => {
+                blink(&mut timer, &mut pipeline.status_led, 6);
+                panic!("cannot save image");
+            }
+            Err(CameraError::Backend(PipelineError::FlatField)) => {
+                blink(&mut timer, &mut pipeline.status_led, 10);
+                panic!("invalid flat field");
+            }
+            Err(CameraError::Backend(PipelineError::Calibration)) => {
+                blink(&mut timer, &mut pipeline.status_led, 11);
+                panic!("cannot calibrate the sensor");
+            }
+        }
     }
 }
 
@@ -1,6 +1,10 @@
-use crate::tiff::write_single_directory_monochrome_tiff;
+use camera_core::{platform::Storage, tiff::DateTime};
 use embedded_hal::spi::SpiDevice;
-use embedded_sdmmc::{Error, Mode::ReadWriteCreate, SdCard, SdCardError, VolumeManager};
+use embedded_sdmmc::{
+    Error,
+    Mode::{ReadOnly, ReadWriteCreate, ReadWriteCreateOrTruncate},
+    SdCard, SdCardError, TimeSource, Timestamp, VolumeManager,
+};
 use rp235x_hal::{Timer, timer::CopyableTimer0};
 
 pub struct Sdmmc<'a, SPI>
@@ -20,48 +24,86 @@ where
         let volume_manager = VolumeManager::new(sdcard, DummyTimesource());
         Self { volume_manager }
     }
+}
 
-    pub fn write_image(
-        &mut self,
-        image_num: u16,
-        width: u16,
-        height: u16,
-        bpp: u16,
-        image: &mut [u8],
-    ) -> Result<(), Error<SdCardError>> {
+impl<SPI> Storage for Sdmmc<'_, SPI>
+where
+    SPI: SpiDevice,
+{
+    type Error = Error<SdCardError>;
+
+    /// The board has no real-time clock, so images are not stamped.
+    fn now(&self) -> Option<DateTime> {
+        None
+    }
+
+    /// Creates the file `file_name` in the root directory, or in the
+    /// directory in front of it, which is created if need be.
+    fn create_file<F>(&mut self, file_name: &str, write: F) -> Result<(), Self::Error>
+    where
+        F: FnOnce(&mut dyn FnMut(&[u8]) -> Result<(), Self::Error>) -> Result<(), Self::Error>,
+    {
         let volume = self
             .volume_manager
             .open_volume(embedded_sdmmc::VolumeIdx(0))?;
         let root_dir = volume.open_root_dir()?;
+        let file = match file_name.split_once('/') {
+            Some((dir_name, file_name)) => {
+                match root_dir.make_dir_in_dir(dir_name) {
+                    Ok(()) | Err(Error::DirAlreadyExists) => {}
+                    Err(e) => return Err(e),
+                }
+                let dir = root_dir.open_dir(dir_name)?;
+                dir.open_file_in_dir(file_name, ReadWriteCreate)?
+            }
+            None => root_dir.open_file_in_dir(file_name, ReadWriteCreate)?,
+        };
+        write(&mut |bytes| file.write(bytes))
+    }
 
-        // file name
-        let mut buf = *b"IM00000.TIF";
-        let mut n = image_num;
-        for b in buf[2..7].iter_mut().rev() {
-            *b += (n % 10) as u8;
-            n /= 10;
-        }
-        let file_name = unsafe { str::from_utf8_unchecked(&buf) };
-
-        let file = root_dir.open_file_in_dir(file_name, ReadWriteCreate)?;
+    /// Writes `bytes` into the file `file_name` in the root directory,
+    /// replacing it if it exists.
+    fn write_file(&mut self, file_name: &str, bytes: &[u8]) -> Result<(), Self::Error> {
+        let volume = self
+            .volume_manager
+            .open_volume(embedded_sdmmc::VolumeIdx(0))?;
+        let root_dir = volume.open_root_dir()?;
+        let file = root_dir.open_file_in_dir(file_name, ReadWriteCreateOrTruncate)?;
+        file.write(bytes)
+    }
 
-        write_single_directory_monochrome_tiff(
-            |bytes| file.write(bytes),
-            width,
-            height,
-            bpp,
-            image,
-        )?;
+    /// Reads the file `file_name` in the root directory into `buffer` and
+    /// returns its length, or `None` if there is no such file. Files longer
+    /// than `buffer` are truncated.
+    fn read_file(
+        &mut self,
+        file_name: &str,
+        buffer: &mut [u8],
+    ) -> Result<Option<usize>, Self::Error> {
+        let volume = self
+            .volume_manager
+            .open_volume(embedded_sdmmc::VolumeIdx(0))?;
+        let root_dir = volume.open_root_dir()?;
+        let file = match root_dir.open_file_in_dir(file_name, ReadOnly) {
+            Ok(file) => file,
+            Err(Error::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
 
-        Ok(())
+        let mut len = 0;
+        while len < buffer.len() && !file.is_eof() {
+            len += file.read(&mut buffer[len..])?;
+        }
+        Ok(Some(len))
     }
 }
 
+/// Stamps the FAT directory entries, which need some time.
 struct DummyTimesource();
 
-impl embedded_sdmmc::TimeSource for DummyTimesource {
-    fn get_timestamp(&self) -> embedded_sdmmc::Timestamp {
-        embedded_sdmmc::Timestamp {
+impl TimeSource for DummyTimesource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
             year_since_1970: 0,
             zero_indexed_month: 0,
             zero_indexed_day: 0,
@@ -0,0 +1,279 @@
+//! Builds a flat-field map for the camera from evenly lit frames it saved.
+//!
+//! The map is written in the format of `camera_core::flatfield`, which the
+//! camera loads.
+//!
+//! The black level is subtracted from every frame before the gains are
+//! computed. It is taken from the BlackLevel tag of DNG files unless it is
+//! given with `--black-level`, which TIFF files need.
+
+mod tiff;
+
+use std::{env, fs, process};
+
+use camera_core::{
+    flatfield::{self, Header},
+    geometry::{ACTIVE, Area},
+};
+
+const UNITY: f64 = flatfield::UNITY as f64;
+
+struct Options {
+    tile: u16,
+    origin: Option<(u16, u16)>,
+    black_level: Option<u16>,
+    output: String,
+    frames: Vec<String>,
+}
+
+fn usage() -> String {
+    "usage: flatfield-tool [--tile N] [--origin LEFT,TOP] [--black-level N] OUTPUT.FFM FRAME..."
+        .into()
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut tile = 1;
+    let mut origin = None;
+    let mut black_level = None;
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tile" => {
+                let value = args.next().ok_or_else(usage)?;
+                tile = value.parse().map_err(|_| format!("invalid tile {value}"))?;
+                if tile == 0 {
+                    return Err("the tile size has to be at least 1".into());
+                }
+            }
+            "--origin" => {
+                let value = args.next().ok_or_else(usage)?;
+                let (left, top) = value
+                    .split_once(',')
+                    .and_then(|(l, t)| Some((l.parse().ok()?, t.parse().ok()?)))
+                    .ok_or_else(|| format!("invalid origin {value}"))?;
+                origin = Some((left, top));
+            }
+            "--black-level" => {
+                let value = args.next().ok_or_else(usage)?;
+                let level = value
+                    .parse()
+                    .map_err(|_| format!("invalid black level {value}"))?;
+                black_level = Some(level);
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() < 2 {
+        return Err(usage());
+    }
+    let output = paths.remove(0);
+    Ok(Options {
+        tile,
+        origin,
+        black_level,
+        output,
+        frames: paths,
+    })
+}
+
+/// Per-pixel sums of the frames above their black level.
+struct Average {
+    width: u16,
+    height: u16,
+    origin: Option<(u16, u16)>,
+    sums: Vec<u64>,
+}
+
+impl Average {
+    fn add(&mut self, frame: &tiff::Frame, black_level: u16) -> Result<(), String> {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            return Err("the frames differ in size".into());
+        }
+        for (sum, pixel) in self.sums.iter_mut().zip(&frame.pixels) {
+            *sum += pixel.saturating_sub(black_level) as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the gains which bring every tile to the mean of the whole frame.
+fn gains(average: &Average, tile: u16) -> Vec<u16> {
+    let width = average.width as usize;
+    let height = average.height as usize;
+    let tile = tile as usize;
+    let columns = width.div_ceil(tile);
+    let rows = height.div_ceil(tile);
+
+    let mut tile_sums = vec![(0u64, 0u64); columns * rows];
+    for (i, sum) in average.sums.iter().enumerate() {
+        let (x, y) = (i % width, i / width);
+        let tile_sum = &mut tile_sums[(y / tile) * columns + x / tile];
+        tile_sum.0 += sum;
+        tile_sum.1 += 1;
+    }
+
+    let mean = average.sums.iter().sum::<u64>() as f64 / average.sums.len() as f64;
+    tile_sums
+        .iter()
+        .map(|&(sum, pixels)| {
+            let tile_mean = sum as f64 / pixels as f64;
+            if tile_mean > 0.0 {
+                (mean / tile_mean * UNITY).round().min(u16::MAX as f64) as u16
+            } else {
+                UNITY as u16
+            }
+        })
+        .collect()
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut average: Option<Average> = None;
+    for path in &options.frames {
+        let bytes = fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+        let frame = tiff::read(&bytes).map_err(|e| format!("{path}: {e}"))?;
+        let average = average.get_or_insert_with(|| Average {
+            width: frame.width,
+            height: frame.height,
+            origin: frame.origin,
+            sums: vec![0; frame.pixels.len()],
+        });
+        let black_level = options.black_level.or(frame.black_level).unwrap_or(0);
+        average
+            .add(&frame, black_level)
+            .map_err(|e| format!("{path}: {e}"))?;
+    }
+    let average = average.ok_or_else(usage)?;
+
+    // Frames cropped to the active area do not record where they start.
+    let cropped = (average.width, average.height) == (ACTIVE.width(), ACTIVE.height());
+    let (left, top) = match options.origin.or(average.origin) {
+        Some(origin) => origin,
+        None if cropped => (ACTIVE.left, ACTIVE.top),
+        None => (0, 0),
+    };
+    let area = Area {
+        top,
+        left,
+        bottom: top + average.height,
+        right: left + average.width,
+    };
+    if !area.is_group_aligned() {
+        return Err("the left and right edges have to be multiples of 4".into());
+    }
+
+    let header = Header {
+        area,
+        tile: options.tile,
+    };
+    let mut words = header.to_words().to_vec();
+    words.extend(gains(&average, options.tile));
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    fs::write(&options.output, bytes).map_err(|e| format!("{}: {e}", options.output))
+}
+
+fn main() {
+    let result = parse_options(env::args().skip(1)).and_then(run);
+    if let Err(e) = result {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        let options = parse(&[
+            "--tile",
+            "16",
+            "--origin",
+            "20,12",
+            "--black-level",
+            "40",
+            "FLAT.FFM",
+            "a.dng",
+            "b.dng",
+        ])
+        .unwrap();
+        assert_eq!(options.tile, 16);
+        assert_eq!(options.origin, Some((20, 12)));
+        assert_eq!(options.black_level, Some(40));
+        assert_eq!(options.output, "FLAT.FFM");
+        assert_eq!(options.frames, ["a.dng", "b.dng"]);
+
+        let options = parse(&["FLAT.FFM", "a.dng"]).unwrap();
+        assert_eq!(options.tile, 1);
+        assert_eq!((options.origin, options.black_level), (None, None));
+    }
+
+    #[test]
+    fn bad_options_are_rejected() {
+        for args in [
+            &[][..],
+            &["FLAT.FFM"],
+            &["--tile", "0", "FLAT.FFM", "a.dng"],
+            &["--tile", "x", "FLAT.FFM", "a.dng"],
+            &["--origin", "20", "FLAT.FFM", "a.dng"],
+            &["--origin", "20,-1", "FLAT.FFM", "a.dng"],
+            &["--black-level", "-1", "FLAT.FFM", "a.dng"],
+            &["FLAT.FFM", "a.dng", "--tile"],
+        ] {
+            assert!(parse(args).is_err(), "{args:?}");
+        }
+    }
+
+    /// A 6x6 average which falls off with the square of the distance from
+    /// its centre, like a lens vignettes.
+    fn vignetted() -> Average {
+        let sums = (0..36)
+            .map(|i| {
+                let (x, y) = ((i % 6) as f64 - 2.5, (i / 6) as f64 - 2.5);
+                (1000.0 - 40.0 * (x * x + y * y)).round() as u64
+            })
+            .collect();
+        Average {
+            width: 6,
+            height: 6,
+            origin: None,
+            sums,
+        }
+    }
+
+    #[test]
+    fn gains_brighten_the_corners() {
+        let average = vignetted();
+        let gains = gains(&average, 2);
+        assert_eq!(gains.len(), 9);
+        let (corners, centre) = ([gains[0], gains[2], gains[6], gains[8]], gains[4]);
+        assert!(corners.iter().all(|&gain| gain > flatfield::UNITY));
+        assert!(centre <= flatfield::UNITY);
+        assert!(corners.iter().all(|&gain| gain > centre));
+        assert_eq!(corners, [corners[0]; 4]);
+
+        // Every tile is brought to the mean of the whole average.
+        let mean = average.sums.iter().sum::<u64>() as f64 / 36.0;
+        for (tile, gain) in gains.iter().enumerate() {
+            let (column, row) = (tile % 3 * 2, tile / 3 * 2);
+            let tile_sum: u64 = [0, 1, 6, 7]
+                .iter()
+                .map(|i| average.sums[row * 6 + column + i])
+                .sum();
+            let corrected = tile_sum as f64 / 4.0 * *gain as f64 / UNITY;
+            assert!((corrected - mean).abs() < 0.5, "tile {tile}");
+        }
+    }
+
+    #[test]
+    fn unlit_tiles_keep_unity_gain() {
+        let mut average = vignetted();
+        average.sums[..2].fill(0);
+        average.sums[6..8].fill(0);
+        assert_eq!(gains(&average, 2)[0], flatfield::UNITY);
+    }
+}
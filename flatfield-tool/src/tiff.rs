@@ -0,0 +1,261 @@
+//! Reads the single-strip TIFF and DNG files the camera writes.
+
+use camera_core::packed;
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_HEIGHT: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_STRIP_BYTE_COUNT: u16 = 279;
+const TAG_BLACK_LEVEL: u16 = 50714;
+const TAG_ACTIVE_AREA: u16 = 50829;
+
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+
+pub struct Frame {
+    pub width: u16,
+    pub height: u16,
+    /// Where the frame starts in the readout, if the file records it. Files
+    /// holding the whole readout are cropped to their ActiveArea tag.
+    pub origin: Option<(u16, u16)>,
+    /// The BlackLevel tag of DNG files, in the units of `pixels`.
+    pub black_level: Option<u16>,
+    pub pixels: Vec<u16>,
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| format!("truncated at {offset}"))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| format!("truncated at {offset}"))
+}
+
+pub fn read(bytes: &[u8]) -> Result<Frame, String> {
+    if bytes.get(..4) != Some(&[b'I', b'I', 0x2A, 0x00]) {
+        return Err("not a little-endian TIFF".into());
+    }
+    let ifd = u32_at(bytes, 4)? as usize;
+
+    let mut width = None;
+    let mut height = None;
+    let mut bits_per_sample = None;
+    let mut strip_offset = None;
+    let mut strip_byte_count = None;
+    let mut active_area = None;
+    let mut black_level = None;
+    for i in 0..u16_at(bytes, ifd)? as usize {
+        let entry = ifd + 2 + i * 12;
+        let tag = u16_at(bytes, entry)?;
+        if tag == TAG_ACTIVE_AREA {
+            let offset = u32_at(bytes, entry + 8)? as usize;
+            let mut edges = [0; 4];
+            for (i, edge) in edges.iter_mut().enumerate() {
+                *edge = u16_at(bytes, offset + i * 2)?;
+            }
+            active_area = Some(edges);
+            continue;
+        }
+        let value = match u16_at(bytes, entry + 2)? {
+            TYPE_SHORT => u16_at(bytes, entry + 8)? as u32,
+            TYPE_LONG => u32_at(bytes, entry + 8)?,
+            _ => continue,
+        };
+        match tag {
+            TAG_IMAGE_WIDTH => width = Some(value as u16),
+            TAG_IMAGE_HEIGHT => height = Some(value as u16),
+            TAG_BITS_PER_SAMPLE => bits_per_sample = Some(value),
+            TAG_STRIP_OFFSETS => strip_offset = Some(value as usize),
+            TAG_STRIP_BYTE_COUNT => strip_byte_count = Some(value as usize),
+            TAG_BLACK_LEVEL => black_level = Some(value as u16),
+            _ => {}
+        }
+    }
+
+    let (Some(width), Some(height), Some(offset), Some(len)) =
+        (width, height, strip_offset, strip_byte_count)
+    else {
+        return Err("missing image tags".into());
+    };
+    let strip = bytes
+        .get(offset..offset + len)
+        .ok_or("truncated image data")?;
+    let count = width as usize * height as usize;
+    let pixels: Vec<u16> = match bits_per_sample {
+        Some(10) => packed::pixels(strip).take(count).collect(),
+        Some(16) => strip
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .take(count)
+            .collect(),
+        bits => return Err(format!("unsupported bits per sample {bits:?}")),
+    };
+    if pixels.len() != count {
+        return Err("truncated image data".into());
+    }
+
+    let frame = Frame {
+        width,
+        height,
+        origin: None,
+        black_level,
+        pixels,
+    };
+    match active_area {
+        Some(edges) => frame.crop(edges),
+        None => Ok(frame),
+    }
+}
+
+impl Frame {
+    /// Crops the frame to the rectangle with the given top, left, bottom
+    /// and right edges.
+    fn crop(self, [top, left, bottom, right]: [u16; 4]) -> Result<Frame, String> {
+        if top > bottom || left > right || bottom > self.height || right > self.width {
+            return Err("invalid active area".into());
+        }
+        let pixels = self
+            .pixels
+            .chunks_exact(self.width as usize)
+            .skip(top as usize)
+            .take((bottom - top) as usize)
+            .flat_map(|row| &row[left as usize..right as usize])
+            .copied()
+            .collect();
+        Ok(Frame {
+            width: right - left,
+            height: bottom - top,
+            origin: Some((left, top)),
+            black_level: self.black_level,
+            pixels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera_core::{
+        geometry::Area,
+        packed::{GROUP_PIXELS, pack},
+        tiff::{
+            Dng, Image, Justification, Metadata, Registers, SampleLayout, write_monochrome_dng,
+            write_single_directory_monochrome_tiff,
+        },
+    };
+
+    const WIDTH: u16 = 8;
+    const HEIGHT: u16 = 4;
+    const READOUT: Area = Area {
+        top: 0,
+        left: 0,
+        bottom: HEIGHT,
+        right: WIDTH,
+    };
+    const ACTIVE: Area = Area {
+        top: 1,
+        left: 4,
+        bottom: 3,
+        right: 8,
+    };
+    const METADATA: Metadata = Metadata {
+        image_number: 1,
+        exposure_us: 10_000,
+        iso: 100,
+        date_time: None,
+        registers: Registers {
+            shutter_width: 42,
+            global_gain: 8,
+            column_start: 0,
+            row_start: 0,
+            column_size: WIDTH - 1,
+            row_size: HEIGHT,
+            analog_offsets: [0; 4],
+        },
+        row_time: 252,
+        frame: 1,
+        timestamp_us: 0,
+    };
+
+    fn pixels() -> Vec<u16> {
+        (0..WIDTH * HEIGHT).map(|i| i * 37 % 1024).collect()
+    }
+
+    /// Writes `pixels()` the way the camera saves them.
+    fn write(active_area: Area, layout: SampleLayout, dng: Option<Dng>) -> Vec<u8> {
+        let frame: Vec<u8> = pixels()
+            .chunks_exact(GROUP_PIXELS)
+            .flat_map(|group| pack(group.try_into().unwrap()))
+            .collect();
+        let image = Image {
+            frame: &frame,
+            frame_width: WIDTH,
+            area: READOUT,
+            active_area,
+        };
+        let mut file = Vec::new();
+        let write_all = |bytes: &[u8]| -> Result<(), ()> {
+            file.extend_from_slice(bytes);
+            Ok(())
+        };
+        match dng {
+            Some(dng) => write_monochrome_dng(write_all, &image, layout, &METADATA, &dng),
+            None => write_single_directory_monochrome_tiff(write_all, &image, layout, &METADATA),
+        }
+        .unwrap();
+        file
+    }
+
+    #[test]
+    fn camera_files_round_trip() {
+        let dng = Dng {
+            black_level: 40,
+            linearization_table: None,
+        };
+        for layout in [
+            SampleLayout::Packed10,
+            SampleLayout::Unpacked16(Justification::Right),
+            SampleLayout::Unpacked16(Justification::Left),
+        ] {
+            let shift = match layout {
+                SampleLayout::Unpacked16(Justification::Left) => 6,
+                _ => 0,
+            };
+            let frame = read(&write(READOUT, layout, Some(dng))).unwrap();
+            assert_eq!((frame.width, frame.height), (WIDTH, HEIGHT), "{layout:?}");
+            assert_eq!(frame.origin, None);
+            assert_eq!(frame.black_level, Some(40 << shift));
+            let expected: Vec<u16> = pixels().iter().map(|pixel| pixel << shift).collect();
+            assert_eq!(frame.pixels, expected, "{layout:?}");
+        }
+
+        let frame = read(&write(READOUT, SampleLayout::Packed10, None)).unwrap();
+        assert_eq!(frame.black_level, None);
+        assert_eq!(frame.pixels, pixels());
+    }
+
+    #[test]
+    fn whole_readouts_are_cropped_to_the_active_area() {
+        let file = write(ACTIVE, SampleLayout::Packed10, None);
+        let frame = read(&file).unwrap();
+        assert_eq!((frame.width, frame.height), (4, 2));
+        assert_eq!(frame.origin, Some((4, 1)));
+        let pixels = pixels();
+        assert_eq!(frame.pixels, [&pixels[12..16], &pixels[20..24]].concat());
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        assert!(read(b"MM\0*\0\0\0\x08").is_err());
+        let file = write(READOUT, SampleLayout::Packed10, None);
+        assert!(read(&file[..file.len() / 2]).is_err());
+        assert!(read(&file[..20]).is_err());
+    }
+}
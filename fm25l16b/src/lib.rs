@@ -0,0 +1,421 @@
+//! A driver for the FM25L16B 2 KiB SPI F-RAM.
+//!
+//! `FM25L16B` works with blocking `embedded-hal` SPI devices and, with the
+//! `async` feature, `FM25L16BAsync` with `embedded-hal-async` ones. The `sim`
+//! feature adds a simulated chip to run code using the F-RAM on the host.
+
+#![no_std]
+
+use embedded_hal::{
+    delay::DelayNs,
+    spi::{Operation, SpiDevice},
+};
+
+const WRITE_ENABLE: u8 = 0x06;
+const WRITE_DISABLE: u8 = 0x04;
+const READ_STATUS: u8 = 0x05;
+const WRITE_STATUS: u8 = 0x01;
+const READ_MEMORY: u8 = 0x03;
+const WRITE_MEMORY: u8 = 0x02;
+const SLEEP: u8 = 0xB9;
+
+/// Bytes of memory.
+pub const SIZE: u16 = 2048;
+/// Time the chip needs to wake up from sleep.
+const RECOVERY_US: u32 = 400;
+
+const STATUS_WPEN: u8 = 1 << 7;
+const STATUS_BP_SHIFT: u32 = 2;
+const STATUS_BP_MASK: u8 = 0b11 << STATUS_BP_SHIFT;
+const STATUS_WEL: u8 = 1 << 1;
+
+/// The part of the memory the chip refuses to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockProtection {
+    None,
+    /// 0x600 to 0x7FF.
+    UpperQuarter,
+    /// 0x400 to 0x7FF.
+    UpperHalf,
+    All,
+}
+
+impl BlockProtection {
+    const fn bits(self) -> u8 {
+        match self {
+            Self::None => 0b00,
+            Self::UpperQuarter => 0b01,
+            Self::UpperHalf => 0b10,
+            Self::All => 0b11,
+        }
+    }
+
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::None,
+            0b01 => Self::UpperQuarter,
+            0b10 => Self::UpperHalf,
+            _ => Self::All,
+        }
+    }
+
+    /// The first protected address, or `SIZE` if nothing is protected.
+    pub const fn start(self) -> u16 {
+        match self {
+            Self::None => SIZE,
+            Self::UpperQuarter => SIZE / 4 * 3,
+            Self::UpperHalf => SIZE / 2,
+            Self::All => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    /// Whether the /WP pin write-protects the status register.
+    pub write_protect_enable: bool,
+    pub block_protection: BlockProtection,
+    /// The write enable latch. It is read-only and cleared by every write.
+    pub write_enabled: bool,
+}
+
+impl Status {
+    const fn from_bits(bits: u8) -> Self {
+        Self {
+            write_protect_enable: bits & STATUS_WPEN != 0,
+            block_protection: BlockProtection::from_bits(
+                (bits & STATUS_BP_MASK) >> STATUS_BP_SHIFT,
+            ),
+            write_enabled: bits & STATUS_WEL != 0,
+        }
+    }
+
+    const fn to_bits(self) -> u8 {
+        let wpen = if self.write_protect_enable {
+            STATUS_WPEN
+        } else {
+            0
+        };
+        wpen | (self.block_protection.bits() << STATUS_BP_SHIFT)
+    }
+}
+
+/// Returns an error unless `len` bytes from `address` are inside the memory.
+/// The chip would wrap around instead.
+const fn check_range<E>(address: u16, len: usize) -> Result<(), FM25L16BError<E>> {
+    if address as usize + len > SIZE as usize {
+        return Err(FM25L16BError::OutOfRange);
+    }
+    Ok(())
+}
+
+/// Returns an error if `len` bytes from `address` overlap the part of the
+/// memory `status` protects.
+const fn check_protection<E>(
+    status: Status,
+    address: u16,
+    len: usize,
+) -> Result<(), FM25L16BError<E>> {
+    if len > 0 && address as usize + len > status.block_protection.start() as usize {
+        return Err(FM25L16BError::Protected);
+    }
+    Ok(())
+}
+
+const fn memory_command(opcode: u8, address: u16) -> [u8; 3] {
+    let [hi, lo] = address.to_be_bytes();
+    [opcode, hi, lo]
+}
+
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+
+pub struct FM25L16B<SPI: SpiDevice> {
+    spi: SPI,
+    asleep: bool,
+}
+
+impl<SPI: SpiDevice> FM25L16B<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        Self { spi, asleep: false }
+    }
+
+    fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        // A sleeping chip ignores the opcode which wakes it.
+        if self.asleep {
+            return Err(FM25L16BError::Asleep);
+        }
+        self.spi.transaction(operations).map_err(FM25L16BError::Spi)
+    }
+
+    fn command(&mut self, opcode: u8) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.transaction(&mut [Operation::Write(&[opcode])])
+    }
+
+    pub fn read_status(&mut self) -> Result<Status, FM25L16BError<SPI::Error>> {
+        let mut bits = [0];
+        self.transaction(&mut [Operation::Write(&[READ_STATUS]), Operation::Read(&mut bits)])?;
+        Ok(Status::from_bits(bits[0]))
+    }
+
+    /// Writes the status register. `status.write_enabled` is ignored.
+    pub fn write_status(&mut self, status: Status) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.command(WRITE_ENABLE)?;
+        self.transaction(&mut [Operation::Write(&[WRITE_STATUS, status.to_bits()])])
+    }
+
+    pub fn set_block_protection(
+        &mut self,
+        block_protection: BlockProtection,
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        let status = self.read_status()?;
+        self.write_status(Status {
+            block_protection,
+            ..status
+        })
+    }
+
+    /// Clears the write enable latch.
+    pub fn write_disable(&mut self) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.command(WRITE_DISABLE)
+    }
+
+    /// Puts the chip into its low power sleep mode until `wake`.
+    pub fn sleep(&mut self) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.command(SLEEP)?;
+        self.asleep = true;
+        Ok(())
+    }
+
+    pub fn wake<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), FM25L16BError<SPI::Error>> {
+        if !self.asleep {
+            return Ok(());
+        }
+        // Selecting the chip wakes it up.
+        self.spi.transaction(&mut []).map_err(FM25L16BError::Spi)?;
+        delay.delay_us(RECOVERY_US);
+        self.asleep = false;
+        Ok(())
+    }
+
+    pub fn read_bytes(
+        &mut self,
+        address: u16,
+        bytes: &mut [u8],
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        check_range(address, bytes.len())?;
+        self.transaction(&mut [
+            Operation::Write(&memory_command(READ_MEMORY, address)),
+            Operation::Read(bytes),
+        ])
+    }
+
+    /// Writes `bytes` from `address`. Fails rather than have the chip ignore
+    /// writes to a block protected part of the memory.
+    pub fn write_bytes(
+        &mut self,
+        address: u16,
+        bytes: &[u8],
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        check_range(address, bytes.len())?;
+        check_protection(self.read_status()?, address, bytes.len())?;
+        self.command(WRITE_ENABLE)?;
+        self.transaction(&mut [
+            Operation::Write(&memory_command(WRITE_MEMORY, address)),
+            Operation::Write(bytes),
+        ])
+    }
+
+    pub fn read<T: NativeByteOrder>(
+        &mut self,
+        address: u16,
+    ) -> Result<T, FM25L16BError<SPI::Error>> {
+        let mut bytes = T::Bytes::default();
+        self.read_bytes(address, bytes.as_mut())?;
+        Ok(T::from_ne_bytes(bytes))
+    }
+
+    pub fn write<T: NativeByteOrder>(
+        &mut self,
+        address: u16,
+        value: T,
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.write_bytes(address, value.to_ne_bytes().as_ref())
+    }
+}
+
+/// The driver for `embedded-hal-async` SPI devices.
+#[cfg(feature = "async")]
+pub struct FM25L16BAsync<SPI: embedded_hal_async::spi::SpiDevice> {
+    spi: SPI,
+    asleep: bool,
+}
+
+#[cfg(feature = "async")]
+impl<SPI: embedded_hal_async::spi::SpiDevice> FM25L16BAsync<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        Self { spi, asleep: false }
+    }
+
+    async fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        if self.asleep {
+            return Err(FM25L16BError::Asleep);
+        }
+        self.spi
+            .transaction(operations)
+            .await
+            .map_err(FM25L16BError::Spi)
+    }
+
+    async fn command(&mut self, opcode: u8) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.transaction(&mut [Operation::Write(&[opcode])]).await
+    }
+
+    pub async fn read_status(&mut self) -> Result<Status, FM25L16BError<SPI::Error>> {
+        let mut bits = [0];
+        self.transaction(&mut [Operation::Write(&[READ_STATUS]), Operation::Read(&mut bits)])
+            .await?;
+        Ok(Status::from_bits(bits[0]))
+    }
+
+    /// Writes the status register. `status.write_enabled` is ignored.
+    pub async fn write_status(&mut self, status: Status) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.command(WRITE_ENABLE).await?;
+        self.transaction(&mut [Operation::Write(&[WRITE_STATUS, status.to_bits()])])
+            .await
+    }
+
+    pub async fn set_block_protection(
+        &mut self,
+        block_protection: BlockProtection,
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        let status = self.read_status().await?;
+        self.write_status(Status {
+            block_protection,
+            ..status
+        })
+        .await
+    }
+
+    /// Clears the write enable latch.
+    pub async fn write_disable(&mut self) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.command(WRITE_DISABLE).await
+    }
+
+    /// Puts the chip into its low power sleep mode until `wake`.
+    pub async fn sleep(&mut self) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.command(SLEEP).await?;
+        self.asleep = true;
+        Ok(())
+    }
+
+    pub async fn wake<D: embedded_hal_async::delay::DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        if !self.asleep {
+            return Ok(());
+        }
+        self.spi
+            .transaction(&mut [])
+            .await
+            .map_err(FM25L16BError::Spi)?;
+        delay.delay_us(RECOVERY_US).await;
+        self.asleep = false;
+        Ok(())
+    }
+
+    pub async fn read_bytes(
+        &mut self,
+        address: u16,
+        bytes: &mut [u8],
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        check_range(address, bytes.len())?;
+        self.transaction(&mut [
+            Operation::Write(&memory_command(READ_MEMORY, address)),
+            Operation::Read(bytes),
+        ])
+        .await
+    }
+
+    /// Writes `bytes` from `address`. Fails rather than have the chip ignore
+    /// writes to a block protected part of the memory.
+    pub async fn write_bytes(
+        &mut self,
+        address: u16,
+        bytes: &[u8],
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        check_range(address, bytes.len())?;
+        check_protection(self.read_status().await?, address, bytes.len())?;
+        self.command(WRITE_ENABLE).await?;
+        self.transaction(&mut [
+            Operation::Write(&memory_command(WRITE_MEMORY, address)),
+            Operation::Write(bytes),
+        ])
+        .await
+    }
+
+    pub async fn read<T: NativeByteOrder>(
+        &mut self,
+        address: u16,
+    ) -> Result<T, FM25L16BError<SPI::Error>> {
+        let mut bytes = T::Bytes::default();
+        self.read_bytes(address, bytes.as_mut()).await?;
+        Ok(T::from_ne_bytes(bytes))
+    }
+
+    pub async fn write<T: NativeByteOrder>(
+        &mut self,
+        address: u16,
+        value: T,
+    ) -> Result<(), FM25L16BError<SPI::Error>> {
+        self.write_bytes(address, value.to_ne_bytes().as_ref())
+            .await
+    }
+}
+
+#[derive(Debug)]
+pub enum FM25L16BError<E> {
+    /// The SPI device failed, including its chip select.
+    Spi(E),
+    /// The bytes do not fit into the memory.
+    OutOfRange,
+    /// The bytes overlap the block protected part of the memory.
+    Protected,
+    /// The chip has to be woken up first.
+    Asleep,
+}
+
+pub trait NativeByteOrder {
+    type Bytes: Default + AsMut<[u8]> + AsRef<[u8]>;
+
+    fn from_ne_bytes(bytes: Self::Bytes) -> Self;
+    fn to_ne_bytes(self) -> Self::Bytes;
+}
+
+macro_rules! impl_native_byte_order {
+    ($($t:ty),+) => {
+        $(
+        impl NativeByteOrder for $t {
+            type Bytes = [u8; size_of::<$t>()];
+
+            fn from_ne_bytes(bytes: Self::Bytes) -> Self {
+                <$t>::from_ne_bytes(bytes)
+            }
+
+            fn to_ne_bytes(self) -> Self::Bytes {
+                <$t>::to_ne_bytes(self)
+            }
+        }
+        )*
+
+    };
+}
+
+impl_native_byte_order!(i8, u8, i16, u16, i32, u32, i64, u64);
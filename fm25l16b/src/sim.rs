@@ -0,0 +1,479 @@
+//! A simulated FM25L16B to run code using the F-RAM on the host.
+//!
+//! The simulator is an `SpiDevice`, so `FM25L16B::new(Simulator::new())`
+//! behaves like the driver on a real chip. With the `async` feature it is an
+//! `embedded-hal-async` one as well, for `FM25L16BAsync`, which completes
+//! every transaction right away. Every transaction is one chip
+//! select: opcodes take effect as on the chip, the write enable latch is
+//! cleared by every completed write, addresses wrap around at the end of
+//! the memory and protected blocks ignore writes.
+
+use core::convert::Infallible;
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+
+use crate::{
+    BlockProtection, READ_MEMORY, READ_STATUS, SIZE, SLEEP, STATUS_BP_MASK, STATUS_BP_SHIFT,
+    STATUS_WEL, STATUS_WPEN, Status, WRITE_DISABLE, WRITE_ENABLE, WRITE_MEMORY, WRITE_STATUS,
+};
+
+/// Address bits the chip decodes; the others are ignored.
+const ADDRESS_MASK: u16 = SIZE - 1;
+
+pub struct Simulator {
+    pub memory: [u8; SIZE as usize],
+    /// The non-volatile status bits.
+    status: u8,
+    write_enabled: bool,
+    asleep: bool,
+    /// Bytes which still reach the memory before the simulated power fails
+    /// and later writes are lost, or `None` to never fail.
+    pub write_budget: Option<usize>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// A chip with every byte erased to zero.
+    pub const fn new() -> Self {
+        Self {
+            memory: [0; SIZE as usize],
+            status: 0,
+            write_enabled: false,
+            asleep: false,
+            write_budget: None,
+        }
+    }
+
+    pub const fn status(&self) -> Status {
+        Status::from_bits(self.status_bits())
+    }
+
+    const fn status_bits(&self) -> u8 {
+        let wel = if self.write_enabled { STATUS_WEL } else { 0 };
+        self.status | wel
+    }
+
+    pub const fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    /// Writes `value` to the memory at `address` unless the write enable
+    /// latch is clear, the address is protected or the power has failed.
+    fn write_memory(&mut self, address: u16, value: u8) {
+        let protection =
+            BlockProtection::from_bits((self.status & STATUS_BP_MASK) >> STATUS_BP_SHIFT);
+        if !self.write_enabled || address >= protection.start() {
+            return;
+        }
+        match &mut self.write_budget {
+            Some(0) => return,
+            Some(budget) => *budget -= 1,
+            None => {}
+        }
+        self.memory[address as usize] = value;
+    }
+}
+
+/// What a chip select has clocked in so far.
+struct Command {
+    opcode: Option<u8>,
+    /// Bytes clocked in after the opcode.
+    index: usize,
+    address: u16,
+}
+
+impl Command {
+    /// Clocks `mosi` into the chip and returns the byte it clocks out.
+    fn clock(&mut self, chip: &mut Simulator, mosi: u8) -> u8 {
+        let Some(opcode) = self.opcode else {
+            self.opcode = Some(mosi);
+            return 0;
+        };
+        let index = self.index;
+        self.index += 1;
+        match (opcode, index) {
+            (READ_MEMORY | WRITE_MEMORY, 0) => {
+                self.address = (mosi as u16) << 8;
+                0
+            }
+            (READ_MEMORY | WRITE_MEMORY, 1) => {
+                self.address = (self.address | mosi as u16) & ADDRESS_MASK;
+                0
+            }
+            (READ_MEMORY, _) => {
+                let miso = chip.memory[self.address as usize];
+                self.address = (self.address + 1) & ADDRESS_MASK;
+                miso
+            }
+            (WRITE_MEMORY, _) => {
+                chip.write_memory(self.address, mosi);
+                self.address = (self.address + 1) & ADDRESS_MASK;
+                0
+            }
+            (READ_STATUS, _) => chip.status_bits(),
+            (WRITE_STATUS, 0) => {
+                if chip.write_enabled {
+                    chip.status = mosi & (STATUS_WPEN | STATUS_BP_MASK);
+                }
+                0
+            }
+            _ => 0,
+        }
+    }
+
+    /// Applies the command when the chip is deselected.
+    fn finish(&self, chip: &mut Simulator) {
+        match self.opcode {
+            Some(WRITE_ENABLE) => chip.write_enabled = true,
+            Some(WRITE_DISABLE) => chip.write_enabled = false,
+            // The latch is cleared once a write has started.
+            Some(WRITE_MEMORY) if self.index > 2 => chip.write_enabled = false,
+            Some(WRITE_STATUS) if self.index > 0 => chip.write_enabled = false,
+            Some(SLEEP) => chip.asleep = true,
+            _ => {}
+        }
+    }
+}
+
+impl ErrorType for Simulator {
+    type Error = Infallible;
+}
+
+impl SpiDevice for Simulator {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+        // Selecting a sleeping chip wakes it up; it ignores the rest of the
+        // transaction and leaves its output floating.
+        if self.asleep {
+            self.asleep = false;
+            for operation in operations {
+                match operation {
+                    Operation::Read(words) | Operation::TransferInPlace(words) => words.fill(0xFF),
+                    Operation::Transfer(read, _) => read.fill(0xFF),
+                    Operation::Write(_) | Operation::DelayNs(_) => {}
+                }
+            }
+            return Ok(());
+        }
+
+        let mut command = Command {
+            opcode: None,
+            index: 0,
+            address: 0,
+        };
+        for operation in operations {
+            match operation {
+                Operation::Read(words) => {
+                    for word in words.iter_mut() {
+                        *word = command.clock(self, 0);
+                    }
+                }
+                Operation::Write(words) => {
+                    for &word in words.iter() {
+                        command.clock(self, word);
+                    }
+                }
+                Operation::Transfer(read, write) => {
+                    let len = read.len().max(write.len());
+                    for i in 0..len {
+                        let miso = command.clock(self, write.get(i).copied().unwrap_or(0));
+                        if let Some(word) = read.get_mut(i) {
+                            *word = miso;
+                        }
+                    }
+                }
+                Operation::TransferInPlace(words) => {
+                    for word in words.iter_mut() {
+                        *word = command.clock(self, *word);
+                    }
+                }
+                Operation::DelayNs(_) => {}
+            }
+        }
+        command.finish(self);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::spi::SpiDevice for Simulator {
+    async fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), Infallible> {
+        SpiDevice::transaction(self, operations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FM25L16B, FM25L16BError, RECOVERY_US};
+
+    /// Runs one chip select clocking out `bytes`.
+    fn select(chip: &mut Simulator, bytes: &[u8]) {
+        chip.transaction(&mut [Operation::Write(bytes)]).unwrap();
+    }
+
+    fn read(chip: &mut Simulator, address: u16, bytes: &mut [u8]) {
+        let [hi, lo] = address.to_be_bytes();
+        chip.transaction(&mut [
+            Operation::Write(&[READ_MEMORY, hi, lo]),
+            Operation::Read(bytes),
+        ])
+        .unwrap();
+    }
+
+    #[test]
+    fn writes_need_the_write_enable_latch() {
+        let mut chip = Simulator::new();
+        select(&mut chip, &[WRITE_MEMORY, 0, 5, 9]);
+        assert_eq!(chip.memory[5], 0);
+
+        select(&mut chip, &[WRITE_ENABLE]);
+        assert!(chip.status().write_enabled);
+        select(&mut chip, &[WRITE_MEMORY, 0, 5, 9]);
+        assert_eq!(chip.memory[5], 9);
+        // Every completed write clears the latch.
+        assert!(!chip.status().write_enabled);
+        select(&mut chip, &[WRITE_MEMORY, 0, 5, 7]);
+        assert_eq!(chip.memory[5], 9);
+
+        select(&mut chip, &[WRITE_ENABLE]);
+        select(&mut chip, &[WRITE_DISABLE]);
+        select(&mut chip, &[WRITE_MEMORY, 0, 5, 7]);
+        assert_eq!(chip.memory[5], 9);
+    }
+
+    #[test]
+    fn status_writes_need_the_write_enable_latch() {
+        let mut chip = Simulator::new();
+        select(&mut chip, &[WRITE_STATUS, STATUS_BP_MASK]);
+        assert_eq!(chip.status().block_protection, BlockProtection::None);
+        select(&mut chip, &[WRITE_ENABLE]);
+        select(&mut chip, &[WRITE_STATUS, STATUS_BP_MASK | STATUS_WEL]);
+        let status = chip.status();
+        assert_eq!(status.block_protection, BlockProtection::All);
+        assert!(!status.write_enabled);
+    }
+
+    #[test]
+    fn addresses_wrap_around() {
+        let mut chip = Simulator::new();
+        select(&mut chip, &[WRITE_ENABLE]);
+        select(&mut chip, &[WRITE_MEMORY, 0x07, 0xFF, 1, 2, 3]);
+        assert_eq!(chip.memory[0x7FF], 1);
+        assert_eq!(chip.memory[..2], [2, 3]);
+
+        // The upper address bits are ignored.
+        let mut bytes = [0; 3];
+        read(&mut chip, 0xFFFF, &mut bytes);
+        assert_eq!(bytes, [1, 2, 3]);
+        read(&mut chip, 0x0800, &mut bytes[..1]);
+        assert_eq!(bytes[0], 2);
+    }
+
+    #[test]
+    fn protected_blocks_ignore_writes() {
+        let mut chip = Simulator::new();
+        select(&mut chip, &[WRITE_ENABLE]);
+        select(
+            &mut chip,
+            &[
+                WRITE_STATUS,
+                BlockProtection::UpperQuarter.bits() << STATUS_BP_SHIFT,
+            ],
+        );
+        select(&mut chip, &[WRITE_ENABLE]);
+        select(&mut chip, &[WRITE_MEMORY, 0x05, 0xFF, 1, 2]);
+        assert_eq!(chip.memory[0x5FF..0x601], [1, 0]);
+    }
+
+    #[test]
+    fn driver_round_trips_native_byte_order() {
+        let mut fm25l16b = FM25L16B::new(Simulator::new());
+        fm25l16b.write(0x10, 0x0102_0304u32).unwrap();
+        fm25l16b.write(0x20, -2i16).unwrap();
+        fm25l16b.write(0x30, u64::MAX - 1).unwrap();
+        assert_eq!(fm25l16b.read::<u32>(0x10).unwrap(), 0x0102_0304);
+        assert_eq!(fm25l16b.read::<i16>(0x20).unwrap(), -2);
+        assert_eq!(fm25l16b.read::<u64>(0x30).unwrap(), u64::MAX - 1);
+
+        let mut bytes = [0; 4];
+        fm25l16b.read_bytes(0x10, &mut bytes).unwrap();
+        assert_eq!(bytes, 0x0102_0304u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn driver_rejects_wrapping_and_protected_writes() {
+        let mut fm25l16b = FM25L16B::new(Simulator::new());
+        assert!(matches!(
+            fm25l16b.write(SIZE - 7, 1u64),
+            Err(FM25L16BError::OutOfRange)
+        ));
+        fm25l16b.write(SIZE - 8, u64::MAX).unwrap();
+
+        fm25l16b
+            .set_block_protection(BlockProtection::UpperHalf)
+            .unwrap();
+        assert_eq!(
+            fm25l16b.read_status().unwrap().block_protection,
+            BlockProtection::UpperHalf
+        );
+        assert!(matches!(
+            fm25l16b.write(0x3FF, 0u16),
+            Err(FM25L16BError::Protected)
+        ));
+        fm25l16b.write(0x3FE, 0xABCDu16).unwrap();
+        assert_eq!(fm25l16b.read::<u16>(0x3FE).unwrap(), 0xABCD);
+        assert_eq!(fm25l16b.read::<u64>(SIZE - 8).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn driver_wakes_the_chip() {
+        struct Delay(u32);
+        impl embedded_hal::delay::DelayNs for Delay {
+            fn delay_ns(&mut self, ns: u32) {
+                self.0 += ns;
+            }
+        }
+
+        let mut fm25l16b = FM25L16B::new(Simulator::new());
+        fm25l16b.write(0, 42u8).unwrap();
+        fm25l16b.sleep().unwrap();
+        assert!(matches!(fm25l16b.read::<u8>(0), Err(FM25L16BError::Asleep)));
+        let mut delay = Delay(0);
+        fm25l16b.wake(&mut delay).unwrap();
+        assert_eq!(delay.0, RECOVERY_US * 1000);
+        assert_eq!(fm25l16b.read::<u8>(0).unwrap(), 42);
+    }
+
+    #[test]
+    fn selecting_a_sleeping_chip_only_wakes_it() {
+        let mut chip = Simulator::new();
+        chip.memory[0] = 7;
+        select(&mut chip, &[SLEEP]);
+        assert!(chip.is_asleep());
+        let mut bytes = [0];
+        read(&mut chip, 0, &mut bytes);
+        assert_eq!(bytes, [0xFF]);
+        assert!(!chip.is_asleep());
+        read(&mut chip, 0, &mut bytes);
+        assert_eq!(bytes, [7]);
+    }
+
+    #[test]
+    fn writes_stop_when_the_power_fails() {
+        let mut chip = Simulator::new();
+        chip.write_budget = Some(3);
+        let mut fm25l16b = FM25L16B::new(&mut chip);
+        fm25l16b.write_bytes(0, &[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(chip.memory[..5], [1, 2, 3, 0, 0]);
+    }
+
+    /// The driver tests above run against `FM25L16BAsync`.
+    #[cfg(feature = "async")]
+    mod async_driver {
+        use core::{
+            future::Future,
+            pin::pin,
+            task::{Context, Poll, Waker},
+        };
+
+        use super::*;
+        use crate::FM25L16BAsync;
+
+        /// Runs `future`, which the simulator never keeps waiting.
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let mut future = pin!(future);
+            let mut context = Context::from_waker(Waker::noop());
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                    return output;
+                }
+            }
+        }
+
+        #[test]
+        fn driver_round_trips_native_byte_order() {
+            block_on(async {
+                let mut fm25l16b = FM25L16BAsync::new(Simulator::new());
+                fm25l16b.write(0x10, 0x0102_0304u32).await.unwrap();
+                fm25l16b.write(0x20, -2i16).await.unwrap();
+                assert_eq!(fm25l16b.read::<u32>(0x10).await.unwrap(), 0x0102_0304);
+                assert_eq!(fm25l16b.read::<i16>(0x20).await.unwrap(), -2);
+
+                let mut bytes = [0; 4];
+                fm25l16b.read_bytes(0x10, &mut bytes).await.unwrap();
+                assert_eq!(bytes, 0x0102_0304u32.to_ne_bytes());
+            });
+        }
+
+        #[test]
+        fn driver_rejects_wrapping_and_protected_writes() {
+            block_on(async {
+                let mut fm25l16b = FM25L16BAsync::new(Simulator::new());
+                assert!(matches!(
+                    fm25l16b.write(SIZE - 7, 1u64).await,
+                    Err(FM25L16BError::OutOfRange)
+                ));
+                fm25l16b.write(SIZE - 8, u64::MAX).await.unwrap();
+
+                fm25l16b
+                    .set_block_protection(BlockProtection::UpperHalf)
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    fm25l16b.read_status().await.unwrap().block_protection,
+                    BlockProtection::UpperHalf
+                );
+                assert!(matches!(
+                    fm25l16b.write(0x3FF, 0u16).await,
+                    Err(FM25L16BError::Protected)
+                ));
+                fm25l16b.write(0x3FE, 0xABCDu16).await.unwrap();
+                assert_eq!(fm25l16b.read::<u16>(0x3FE).await.unwrap(), 0xABCD);
+                assert_eq!(fm25l16b.read::<u64>(SIZE - 8).await.unwrap(), u64::MAX);
+            });
+        }
+
+        #[test]
+        fn driver_wakes_the_chip() {
+            struct Delay(u32);
+            impl embedded_hal_async::delay::DelayNs for Delay {
+                async fn delay_ns(&mut self, ns: u32) {
+                    self.0 += ns;
+                }
+            }
+
+            block_on(async {
+                let mut fm25l16b = FM25L16BAsync::new(Simulator::new());
+                fm25l16b.write(0, 42u8).await.unwrap();
+                fm25l16b.sleep().await.unwrap();
+                assert!(matches!(
+                    fm25l16b.read::<u8>(0).await,
+                    Err(FM25L16BError::Asleep)
+                ));
+                let mut delay = Delay(0);
+                fm25l16b.wake(&mut delay).await.unwrap();
+                assert_eq!(delay.0, RECOVERY_US * 1000);
+                assert_eq!(fm25l16b.read::<u8>(0).await.unwrap(), 42);
+            });
+        }
+
+        #[test]
+        fn writes_stop_when_the_power_fails() {
+            let mut chip = Simulator::new();
+            chip.write_budget = Some(3);
+            block_on(async {
+                let mut fm25l16b = FM25L16BAsync::new(&mut chip);
+                fm25l16b.write_bytes(0, &[1, 2, 3, 4, 5]).await.unwrap();
+            });
+            assert_eq!(chip.memory[..5], [1, 2, 3, 0, 0]);
+        }
+    }
+}
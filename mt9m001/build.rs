@@ -0,0 +1,984 @@
+//! Generates the register constants, the bit-field structs and enums and the
+//! sensor struct from the sensor description file into `$OUT_DIR/mt9m001.rs`,
+//! which `src/lib.rs` includes.
+
+use std::{env, fs, path::Path};
+
+use serde::Deserialize;
+
+const DESCRIPTION_FILE: &str = "mt9m001.json";
+
+#[derive(Deserialize)]
+struct SensorDescription {
+    description: Description,
+    registers: Vec<Register>,
+}
+
+#[derive(Deserialize)]
+struct Description {
+    name: String,
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct Register {
+    address: String,
+    name: String,
+    default: Option<String>,
+    documentation: Vec<String>,
+    /// The data format of a register with bits, like `"1000 dddd 00dd dd00"`
+    /// for bits which are fixed to 1 or 0 and data bits.
+    format: Option<String>,
+    #[serde(default)]
+    access: Access,
+    /// What a `command` register does when written.
+    command: Option<Command>,
+    /// The value a register without bits holds.
+    value: Option<ValueType>,
+    /// Whether the sensor changes the register by itself, so that its last
+    /// written value must not be cached.
+    #[serde(default)]
+    volatile: bool,
+    /// The registers whose values change when this one is written.
+    #[serde(default)]
+    invalidates: Vec<String>,
+    #[serde(default)]
+    bits: Vec<Bit>,
+}
+
+/// How a register may be accessed.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Access {
+    /// Read-only.
+    Ro,
+    #[default]
+    Rw,
+    /// Write-only.
+    Wo,
+    /// Written to trigger an action rather than to hold a value.
+    Command,
+}
+
+/// The type of a register value, stored in the data bits of the register's
+/// data format.
+#[derive(Deserialize, PartialEq, Eq)]
+struct ValueType {
+    /// The name of a type several registers share. Registers without one
+    /// get a type named after them.
+    #[serde(rename = "type")]
+    name: Option<String>,
+    #[serde(default)]
+    documentation: Vec<String>,
+    #[serde(default)]
+    encoding: Encoding,
+    unit: Option<String>,
+    min: Option<i32>,
+    max: Option<i32>,
+    parity: Option<Parity>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Encoding {
+    #[default]
+    Unsigned,
+    /// The highest data bit is the sign, the others the magnitude.
+    SignMagnitude,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Parity {
+    Odd,
+    Even,
+}
+
+#[derive(Deserialize)]
+struct Command {
+    name: String,
+    documentation: Vec<String>,
+    /// The values written to the register in order.
+    writes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Bit {
+    /// A single bit (`"4"`) or an inclusive range of bits (`"15:8"`).
+    bit_position: String,
+    name: String,
+    documentation: Vec<String>,
+    /// Named values of an enumerated field, which must cover all of its
+    /// bits.
+    #[serde(default)]
+    values: Vec<Value>,
+    /// Whether the sensor resets the bit to 0 once it has acted on it.
+    #[serde(default)]
+    self_clearing: bool,
+}
+
+#[derive(Deserialize)]
+struct Value {
+    value: u32,
+    name: String,
+    documentation: Vec<String>,
+}
+
+/// `name` in snake case, e.g. `"Read Options 1"` becomes `read_options_1`.
+fn snake_case(name: &str) -> String {
+    let words: Vec<&str> = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let snake = words.join("_").to_ascii_lowercase();
+    match snake.starts_with(|c: char| c.is_ascii_digit()) {
+        true => format!("_{snake}"),
+        false => snake,
+    }
+}
+
+/// `name` in Pascal case, e.g. `"Read Options 1"` becomes `ReadOptions1`.
+fn pascal_case(name: &str) -> String {
+    let pascal: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word[..1].to_ascii_uppercase() + &word[1..].to_ascii_lowercase())
+        .collect();
+    match pascal.starts_with(|c: char| c.is_ascii_digit()) {
+        true => format!("_{pascal}"),
+        false => pascal,
+    }
+}
+
+fn parse_hex(value: &str) -> u32 {
+    value
+        .strip_prefix("0x")
+        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+        .unwrap_or_else(|| panic!("{value} is not a hexadecimal number"))
+}
+
+fn parse_bit(bit: &str) -> u32 {
+    match bit.trim().parse() {
+        Ok(bit) if bit < 16 => bit,
+        _ => panic!("{bit} is not a bit of a 16-bit register"),
+    }
+}
+
+/// Documentation lines as doc comments indented by `indent`.
+fn doc(indent: &str, documentation: &[String]) -> String {
+    documentation
+        .iter()
+        .map(|line| format!("{indent}/// {line}\n"))
+        .collect()
+}
+
+impl Bit {
+    /// The lowest bit and the width of the field.
+    fn range(&self) -> (u32, u32) {
+        match self.bit_position.split_once(':') {
+            Some((high, low)) => {
+                let (high, low) = (parse_bit(high), parse_bit(low));
+                assert!(high > low, "{} is not a range of bits", self.bit_position);
+                (low, high - low + 1)
+            }
+            None => (parse_bit(&self.bit_position), 1),
+        }
+    }
+
+    fn mask(&self) -> u32 {
+        let (low, width) = self.range();
+        ((1u32 << width) - 1) << low
+    }
+
+    fn render_enum(&self) -> String {
+        let (_, width) = self.range();
+        let mut values: Vec<u32> = self.values.iter().map(|value| value.value).collect();
+        values.sort_unstable();
+        assert!(
+            values.iter().copied().eq(0..1 << width),
+            "the values of {} do not cover its bits",
+            self.name
+        );
+
+        let name = pascal_case(&self.name);
+        let variants: String = self
+            .values
+            .iter()
+            .map(|value| {
+                format!(
+                    "{}    {} = {},\n",
+                    doc("    ", &value.documentation),
+                    pascal_case(&value.name),
+                    value.value
+                )
+            })
+            .collect();
+        format!(
+            "{}#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum {name} {{
+{variants}}}
+
+",
+            doc("", &self.documentation)
+        )
+    }
+
+    fn render(&self) -> String {
+        let name = snake_case(&self.name);
+        let doc = doc("    ", &self.documentation);
+        let (low, width) = self.range();
+        let mask = self.mask();
+        let field_mask = mask >> low;
+        let field = match low {
+            0 => "self.value".to_string(),
+            _ => format!("(self.value >> {low})"),
+        };
+        let shifted = |value: &str| match low {
+            0 => value.to_string(),
+            _ => format!("(({value}) << {low})"),
+        };
+
+        if !self.values.is_empty() {
+            let type_name = pascal_case(&self.name);
+            let value = shifted("value as u16");
+            let (last, values) = self.values.split_last().unwrap();
+            let arms: String = values
+                .iter()
+                .map(|value| {
+                    format!(
+                        "            {} => {type_name}::{},\n",
+                        value.value,
+                        pascal_case(&value.name)
+                    )
+                })
+                .collect();
+            return format!(
+                "
+{doc}    pub const fn set_{name}(mut self, value: {type_name}) -> Self {{
+        self.value = (self.value & !0x{mask:04X}) | {value};
+        self
+    }}
+
+{doc}    pub const fn get_{name}(&self) -> {type_name} {{
+        match {field} & 0x{field_mask:04X} {{
+{arms}            _ => {type_name}::{last},
+        }}
+    }}
+",
+                last = pascal_case(&last.name),
+            );
+        }
+
+        if width == 1 {
+            let value = shifted("value as u16");
+            return format!(
+                "
+{doc}    pub const fn set_{name}(mut self, value: bool) -> Self {{
+        self.value = (self.value & !0x{mask:04X}) | {value};
+        self
+    }}
+
+{doc}    pub const fn get_{name}(&self) -> bool {{
+        (self.value & 0x{mask:04X}) != 0
+    }}
+"
+            );
+        }
+
+        let (ty, value, cast) = match width {
+            ..=8 => ("u8", shifted("value as u16"), " as u8"),
+            _ => ("u16", shifted("value"), ""),
+        };
+        let get = format!(
+            "
+{doc}    pub const fn get_{name}(&self) -> {ty} {{
+        ({field} & 0x{field_mask:04X}){cast}
+    }}
+"
+        );
+        // Fields as wide as their type take any value.
+        if width == 8 || width == 16 {
+            return format!(
+                "
+{doc}    pub const fn set_{name}(mut self, value: {ty}) -> Self {{
+        self.value = (self.value & !0x{mask:04X}) | {value};
+        self
+    }}
+{get}"
+            );
+        }
+        format!(
+            "
+{doc}    pub const fn set_{name}(mut self, value: {ty}) -> Result<Self, InvalidValue> {{
+        if value > 0x{field_mask:X} {{
+            return Err(InvalidValue);
+        }}
+        self.value = (self.value & !0x{mask:04X}) | {value};
+        Ok(self)
+    }}
+{get}"
+        )
+    }
+}
+
+impl ValueType {
+    /// The lowest data bit and the number of data bits.
+    fn bits(data: u32) -> (u32, u32) {
+        let (low, width) = (data.trailing_zeros(), data.count_ones());
+        assert!(
+            data >> low == (1 << width) - 1,
+            "the data bits of a value are not contiguous"
+        );
+        (low, width)
+    }
+
+    /// The Rust type and the lowest and highest value of the type.
+    fn range(&self, width: u32) -> (&'static str, i32, i32) {
+        match self.encoding {
+            Encoding::Unsigned => ("u16", 0, (1 << width) - 1),
+            Encoding::SignMagnitude => {
+                let max = (1 << (width - 1)) - 1;
+                ("i16", -max, max)
+            }
+        }
+    }
+
+    fn decode(&self, register: u32, data: u32) -> i32 {
+        let (low, width) = Self::bits(data);
+        let value = ((register & data) >> low) as i32;
+        match self.encoding {
+            Encoding::Unsigned => value,
+            Encoding::SignMagnitude => {
+                let magnitude = value & ((1 << (width - 1)) - 1);
+                match value >> (width - 1) {
+                    0 => magnitude,
+                    _ => -magnitude,
+                }
+            }
+        }
+    }
+
+    fn is_valid(&self, value: i32, data: u32) -> bool {
+        let (_, width) = Self::bits(data);
+        let (_, min, max) = self.range(width);
+        let parity = match self.parity {
+            Some(Parity::Odd) => value % 2 == 1,
+            Some(Parity::Even) => value % 2 == 0,
+            None => true,
+        };
+        value >= self.min.unwrap_or(min) && value <= self.max.unwrap_or(max) && parity
+    }
+
+    /// `registers` share this type, described by the first of them.
+    fn render(&self, name: &str, registers: &[&Register]) -> String {
+        let (data, fixed) = registers[0].format();
+        assert!(fixed == 0, "{name} has bits fixed to 1");
+        let (low, width) = Self::bits(data);
+        let (ty, type_min, type_max) = self.range(width);
+        let (min, max) = (self.min.unwrap_or(type_min), self.max.unwrap_or(type_max));
+        assert!(
+            type_min <= min && min <= max && max <= type_max,
+            "the range of {name} does not fit into its data bits"
+        );
+        assert!(
+            self.parity.is_none() || self.encoding == Encoding::Unsigned,
+            "{name} has a parity but is signed"
+        );
+
+        // Comparisons with the bounds of the Rust type itself are always
+        // false.
+        let (ty_min, ty_max) = match ty {
+            "u16" => (u16::MIN as i32, u16::MAX as i32),
+            _ => (i16::MIN as i32, i16::MAX as i32),
+        };
+        let mut checks = Vec::new();
+        if min > ty_min {
+            checks.push("value < Self::MIN");
+        }
+        if max < ty_max {
+            checks.push("value > Self::MAX");
+        }
+        match self.parity {
+            Some(Parity::Odd) => checks.push("value.is_multiple_of(2)"),
+            Some(Parity::Even) => checks.push("!value.is_multiple_of(2)"),
+            None => {}
+        }
+        let new = match checks.is_empty() {
+            true => format!(
+                "
+    pub const fn new(value: {ty}) -> Self {{
+        Self(value)
+    }}
+"
+            ),
+            false => format!(
+                "
+    pub const fn new(value: {ty}) -> Result<Self, InvalidValue> {{
+        if {} {{
+            return Err(InvalidValue);
+        }}
+        Ok(Self(value))
+    }}
+",
+                checks.join(" || ")
+            ),
+        };
+
+        let shift = |value: &str| match low {
+            0 => value.to_string(),
+            _ => format!("({value} << {low})"),
+        };
+        let field = |mask: u32| match low {
+            0 => format!("register & 0x{mask:04X}"),
+            _ => format!("(register >> {low}) & 0x{:04X}", mask >> low),
+        };
+        let (from_register, register) = match self.encoding {
+            Encoding::Unsigned => (
+                format!("Self({})", field(data)),
+                match low {
+                    0 => "        self.0".to_string(),
+                    _ => format!("        self.0 << {low}"),
+                },
+            ),
+            Encoding::SignMagnitude => {
+                let sign = 1u32 << (low + width - 1);
+                (
+                    format!(
+                        "let magnitude = ({}) as i16;
+        match register & 0x{sign:04X} {{
+            0 => Self(magnitude),
+            _ => Self(-magnitude),
+        }}",
+                        field(data & !sign)
+                    ),
+                    format!(
+                        "        let magnitude = {};
+        match self.0 < 0 {{
+            true => 0x{sign:04X} | magnitude,
+            false => magnitude,
+        }}",
+                        shift("self.0.unsigned_abs()")
+                    ),
+                )
+            }
+        };
+
+        // The power-up value, if all registers of the type share it.
+        let defaults: Vec<Option<u32>> = registers.iter().map(|r| r.default()).collect();
+        let default = match defaults[0] {
+            Some(default) if defaults.iter().all(|d| *d == defaults[0]) => {
+                let value = self.decode(default, data);
+                assert!(
+                    self.is_valid(value, data),
+                    "the default of {name} is not valid"
+                );
+                format!("    pub const DEFAULT: Self = Self({value});\n")
+            }
+            _ => String::new(),
+        };
+
+        let documentation = match self.documentation.is_empty() {
+            true => &registers[0].documentation,
+            false => &self.documentation,
+        };
+        let mut valid = match self.parity {
+            Some(Parity::Odd) => format!("Odd values from {min} to {max}"),
+            Some(Parity::Even) => format!("Even values from {min} to {max}"),
+            None => format!("Values from {min} to {max}"),
+        };
+        if let Some(unit) = &self.unit {
+            valid += &format!(", in {unit}");
+        }
+        format!(
+            "{}///
+/// {valid}.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct {name}({ty});
+
+impl {name} {{
+    pub const MIN: {ty} = {min};
+    pub const MAX: {ty} = {max};
+{default}{new}
+    pub const fn get(self) -> {ty} {{
+        self.0
+    }}
+
+    /// The value a register holds.
+    pub const fn from_register(register: u16) -> Self {{
+        {from_register}
+    }}
+
+    /// The register holding the value.
+    pub const fn register(self) -> u16 {{
+{register}
+    }}
+}}
+
+",
+            doc("", documentation)
+        )
+    }
+}
+
+impl Register {
+    fn const_name(&self) -> String {
+        snake_case(&self.name).to_ascii_uppercase()
+    }
+
+    fn render_const(&self) -> String {
+        let address = parse_hex(&self.address);
+        assert!(address <= 0xFF, "{} has no 8-bit address", self.name);
+        format!(
+            "    pub const {}: u8 = 0x{address:02X};\n",
+            self.const_name()
+        )
+    }
+
+    fn default(&self) -> Option<u32> {
+        let default = parse_hex(self.default.as_deref()?);
+        assert!(default <= 0xFFFF, "{} has no 16-bit default", self.name);
+        Some(default)
+    }
+
+    /// The data bits and the values of the fixed bits.
+    fn format(&self) -> (u32, u32) {
+        let format = self
+            .format
+            .as_deref()
+            .unwrap_or_else(|| panic!("{} has no data format", self.name));
+        let bits: Vec<char> = format.chars().filter(|c| *c != ' ').collect();
+        assert!(bits.len() == 16, "{format} is not a 16-bit data format");
+        bits.iter()
+            .rev()
+            .enumerate()
+            .fold((0, 0), |(data, fixed), (bit, c)| match c {
+                'd' => (data | 1 << bit, fixed),
+                '1' => (data, fixed | 1 << bit),
+                '0' => (data, fixed),
+                _ => panic!("{format} is not a data format"),
+            })
+    }
+
+    fn render_struct(&self) -> String {
+        let name = pascal_case(&self.name);
+        let default = self
+            .default()
+            .unwrap_or_else(|| panic!("{} has bits but no default value", self.name));
+        let (data, fixed) = self.format();
+        assert!(
+            default & !data == fixed,
+            "the default of {} does not match its data format",
+            self.name
+        );
+        self.bits.iter().fold(0, |used, bit| {
+            assert!(
+                bit.mask() & !data == 0 && bit.mask() & used == 0,
+                "{} of {} is not on free data bits",
+                bit.name,
+                self.name
+            );
+            used | bit.mask()
+        });
+
+        let value = match (data, fixed) {
+            (0xFFFF, _) => "value".to_string(),
+            (_, 0) => format!("value & 0x{data:04X}"),
+            _ => format!("(value & 0x{data:04X}) | 0x{fixed:04X}"),
+        };
+        let functions: String = self.bits.iter().map(Bit::render).collect();
+        format!(
+            "#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct {name} {{
+    value: u16,
+}}
+
+impl {name} {{
+    pub const DEFAULT: Self = Self::new(0x{default:04X});
+
+    /// Bits the data format fixes always hold their fixed value.
+    pub const fn new(value: u16) -> Self {{
+        Self {{ value: {value} }}
+    }}
+{functions}}}
+
+"
+        )
+    }
+
+    fn value_type_name(&self) -> Option<String> {
+        let value = self.value.as_ref()?;
+        Some(pascal_case(value.name.as_deref().unwrap_or(&self.name)))
+    }
+
+    /// Whether the driver caches the register's last read or written value
+    /// for `modify_*`.
+    fn is_cached(&self) -> bool {
+        self.access == Access::Rw && !self.bits.is_empty() && !self.volatile
+    }
+
+    /// The bits which the sensor clears by itself.
+    fn self_clearing_mask(&self) -> u32 {
+        self.bits
+            .iter()
+            .filter(|bit| bit.self_clearing)
+            .map(Bit::mask)
+            .fold(0, |mask, bit| mask | bit)
+    }
+
+    /// The accessors of the register, where `cache_index` is its index in
+    /// the cache if it is cached.
+    fn render_accessors(&self, cache_index: Option<usize>) -> String {
+        let name = snake_case(&self.name);
+        let address = self.const_name();
+        let doc = doc("    ", &self.documentation);
+
+        if self.access == Access::Command {
+            return self.render_command();
+        }
+        assert!(
+            self.command.is_none(),
+            "{} has a command but is not a command register",
+            self.name
+        );
+
+        let (get, set) = if let Some(type_name) = self.value_type_name() {
+            (
+                format!(
+                    "
+{doc}    pub fn get_{name}(&mut self) -> Result<{type_name}, I2C::Error> {{
+        Ok({type_name}::from_register(self.get_u16({address})?))
+    }}
+"
+                ),
+                format!(
+                    "
+{doc}    pub fn set_{name}(&mut self, value: {type_name}) -> Result<(), I2C::Error> {{
+        self.set_u16({address}, value.register())
+    }}
+"
+                ),
+            )
+        } else if self.bits.is_empty() {
+            (
+                format!(
+                    "
+{doc}    pub fn get_{name}(&mut self) -> Result<u16, I2C::Error> {{
+        self.get_u16({address})
+    }}
+"
+                ),
+                format!(
+                    "
+{doc}    pub fn set_{name}(&mut self, value: u16) -> Result<(), I2C::Error> {{
+        self.set_u16({address}, value)
+    }}
+"
+                ),
+            )
+        } else {
+            let struct_name = pascal_case(&self.name);
+            (
+                format!(
+                    "
+{doc}    pub fn get_{name}(&mut self) -> Result<{struct_name}, I2C::Error> {{
+        Ok({struct_name}::new(self.get_u16({address})?))
+    }}
+"
+                ),
+                format!(
+                    "
+{doc}    pub fn set_{name}(&mut self, value: &{struct_name}) -> Result<(), I2C::Error> {{
+        self.set_u16({address}, value.value)
+    }}
+"
+                ),
+            )
+        };
+        match self.access {
+            Access::Ro => get,
+            Access::Wo => set,
+            _ if self.bits.is_empty() => get + &set,
+            _ => get + &set + &self.render_modify(cache_index),
+        }
+    }
+
+    fn render_modify(&self, cache_index: Option<usize>) -> String {
+        let name = snake_case(&self.name);
+        let address = self.const_name();
+        let struct_name = pascal_case(&self.name);
+        let read = match cache_index {
+            Some(index) => format!(
+                "match self.cached({index}) {{
+            Some(value) => value,
+            None => self.get_u16({address})?,
+        }}"
+            ),
+            None => format!("self.get_u16({address})?"),
+        };
+        format!(
+            "
+    /// Reads {}, updates it with `f` and writes it back. The read is skipped
+    /// if the driver caches register values and knows the current value.
+    pub fn modify_{name}<F>(&mut self, f: F) -> Result<(), I2C::Error>
+    where
+        F: FnOnce({struct_name}) -> {struct_name},
+    {{
+        let value = {read};
+        self.set_u16({address}, f({struct_name}::new(value)).value)
+    }}
+",
+            self.name
+        )
+    }
+
+    fn render_command(&self) -> String {
+        let command = self
+            .command
+            .as_ref()
+            .unwrap_or_else(|| panic!("{} is a command register without a command", self.name));
+        assert!(
+            !command.writes.is_empty(),
+            "{} writes nothing",
+            command.name
+        );
+        let address = self.const_name();
+        let writes: Vec<String> = command
+            .writes
+            .iter()
+            .map(|value| {
+                let value = parse_hex(value);
+                assert!(value <= 0xFFFF, "{} writes more than 16 bits", command.name);
+                format!("        self.set_u16({address}, 0x{value:04X})?;\n")
+            })
+            .collect();
+        format!(
+            "
+{}    pub fn {}(&mut self) -> Result<(), I2C::Error> {{
+        self.forget_all();
+{}        Ok(())
+    }}
+",
+            doc("    ", &command.documentation),
+            snake_case(&command.name),
+            writes.concat()
+        )
+    }
+}
+
+/// The sensor struct with its constructors, the register access and the
+/// cache of `cached` registers, left open for the accessors.
+fn render_sensor(sensor: &SensorDescription, cached: &[&Register]) -> String {
+    let name = sensor.description.name.to_ascii_uppercase();
+    let size = cached.len();
+
+    let index_of = |register: &Register, name: &str| {
+        cached
+            .iter()
+            .position(|cached| cached.name == name)
+            .unwrap_or_else(|| panic!("{} invalidates {name}, which is not cached", register.name))
+    };
+    // One arm for every register which is cached or invalidates others, as
+    // a register can be both.
+    let mut remember = String::new();
+    for register in &sensor.registers {
+        let mut statements = Vec::new();
+        if let Some(index) = cached
+            .iter()
+            .position(|cached| cached.name == register.name)
+        {
+            let mask = register.self_clearing_mask();
+            let value = if mask == 0 {
+                "value".to_string()
+            } else {
+                format!("value & !0x{mask:04X}")
+            };
+            statements.push(format!("cache[{index}] = Some({value})"));
+        }
+        for name in &register.invalidates {
+            assert!(*name != register.name, "{name} invalidates itself");
+            statements.push(format!("cache[{}] = None", index_of(register, name)));
+        }
+        let arm = match statements.as_slice() {
+            [] => continue,
+            [statement] => format!("{statement},"),
+            _ => format!(
+                "{{\n                {};\n            }}",
+                statements.join(";\n                ")
+            ),
+        };
+        remember += &format!("            {} => {arm}\n", register.const_name());
+    }
+    assert!(!remember.is_empty(), "no register is cached");
+
+    format!(
+        "pub struct {name}<I2C>
+where
+    I2C: I2c,
+{{
+    i2c: I2C,
+    /// The last read or written values of the cached registers, if caching.
+    cache: Option<[Option<u16>; {size}]>,
+}}
+
+impl<I2C> {name}<I2C>
+where
+    I2C: I2c,
+{{
+    /// A driver which reads every register it modifies.
+    pub const fn new(i2c: I2C) -> Self {{
+        Self {{ i2c, cache: None }}
+    }}
+
+    /// A driver which remembers the values of the registers it reads and
+    /// writes, so that `modify_*` only reads a register the first time.
+    /// Nothing else may write the sensor's registers while it is in use.
+    pub const fn new_cached(i2c: I2C) -> Self {{
+        Self {{
+            i2c,
+            cache: Some([None; {size}]),
+        }}
+    }}
+
+    fn cached(&self, index: usize) -> Option<u16> {{
+        self.cache.and_then(|cache| cache[index])
+    }}
+
+    /// Updates the cache after `value` was read from or written to `reg`.
+    fn remember(&mut self, reg: u8, value: u16) {{
+        let Some(cache) = &mut self.cache else {{
+            return;
+        }};
+        match reg {{
+{remember}            _ => {{}}
+        }}
+    }}
+
+    fn forget_all(&mut self) {{
+        if let Some(cache) = &mut self.cache {{
+            *cache = [None; {size}];
+        }}
+    }}
+
+    fn get_u16(&mut self, reg: u8) -> Result<u16, I2C::Error> {{
+        self.i2c.write(SENSOR_ADDRESS, &[reg])?;
+        let mut bytes = [0; 2];
+        self.i2c.read(SENSOR_ADDRESS, &mut bytes)?;
+
+        let value = u16::from_be_bytes(bytes);
+        self.remember(reg, value);
+        Ok(value)
+    }}
+
+    fn set_u16(&mut self, reg: u8, value: u16) -> Result<(), I2C::Error> {{
+        let bytes = value.to_be_bytes();
+        self.i2c.write(SENSOR_ADDRESS, &[reg, bytes[0], bytes[1]])?;
+        self.remember(reg, value);
+        Ok(())
+    }}
+"
+    )
+}
+
+fn render(sensor: &SensorDescription) -> String {
+    let mut out = format!("// This file was automatically generated from {DESCRIPTION_FILE}\n\n");
+
+    let address = parse_hex(&sensor.description.address);
+    out += &format!(
+        "/// The I2C address of the sensor and the addresses of its registers.
+pub mod registers {{
+    pub const SENSOR_ADDRESS: u8 = 0x{address:02X};
+"
+    );
+    for register in &sensor.registers {
+        out += &register.render_const();
+    }
+    out += "}\n\nuse registers::*;\n";
+
+    let defaults: Vec<String> = sensor
+        .registers
+        .iter()
+        .filter_map(|register| {
+            let default = register.default()?;
+            Some(format!(
+                "    ({}, 0x{default:04X}),\n",
+                register.const_name()
+            ))
+        })
+        .collect();
+    out += &format!(
+        "
+/// Power-up values of the registers which have one.
+#[cfg(any(test, feature = \"sim\"))]
+const DEFAULTS: [(u8, u16); {}] = [
+{}];
+
+",
+        defaults.len(),
+        defaults.concat(),
+    );
+
+    for bit in sensor.registers.iter().flat_map(|register| &register.bits) {
+        if !bit.values.is_empty() {
+            out += &bit.render_enum();
+        }
+    }
+    let mut value_types: Vec<(String, Vec<&Register>)> = Vec::new();
+    for register in &sensor.registers {
+        let Some(name) = register.value_type_name() else {
+            continue;
+        };
+        assert!(
+            register.bits.is_empty(),
+            "{} has both bits and a value",
+            register.name
+        );
+        match value_types
+            .iter_mut()
+            .find(|(type_name, _)| *type_name == name)
+        {
+            Some((_, registers)) => {
+                assert!(
+                    registers[0].value == register.value && registers[0].format == register.format,
+                    "the registers of {name} differ in their value or data format"
+                );
+                registers.push(register);
+            }
+            None => value_types.push((name, vec![register])),
+        }
+    }
+    for (name, registers) in &value_types {
+        out += &registers[0].value.as_ref().unwrap().render(name, registers);
+    }
+    for register in &sensor.registers {
+        if !register.bits.is_empty() {
+            out += &register.render_struct();
+        }
+    }
+
+    let cached: Vec<&Register> = sensor
+        .registers
+        .iter()
+        .filter(|register| register.is_cached())
+        .collect();
+    out += &render_sensor(sensor, &cached);
+    for register in &sensor.registers {
+        let cache_index = cached
+            .iter()
+            .position(|cached| cached.address == register.address);
+        out += &register.render_accessors(cache_index);
+    }
+    out += "}\n";
+    out
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={DESCRIPTION_FILE}");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let json = fs::read_to_string(DESCRIPTION_FILE)
+        .unwrap_or_else(|error| panic!("cannot read {DESCRIPTION_FILE}: {error}"));
+    let sensor: SensorDescription = serde_json::from_str(&json)
+        .unwrap_or_else(|error| panic!("cannot parse {DESCRIPTION_FILE}: {error}"));
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("mt9m001.rs"), render(&sensor)).unwrap();
+}
@@ -0,0 +1,379 @@
+//! A simulated MT9M001 to run code using the sensor on the host.
+//!
+//! The simulator is an `I2c` device answering at the sensor address, so
+//! `MT9M001::new(Simulator::new())` behaves like the driver on a real chip.
+//! It holds the register file with its power-up defaults and models the
+//! registers which do more than store a value: Chip Version is read-only,
+//! Frame Restart clears itself, Chip Enable and Synchronize Changes are
+//! mirrored between Reg0x07 and Reg0xF1, Global Gain sets the four color
+//! gains and Reset holds the chip in its power-up state until it is
+//! cleared again. Like on the chip, the register address advances after
+//! every register read or written, and unused addresses read as zero.
+
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+use crate::{
+    CHIP_ENABLE, CHIP_VERSION, DEFAULTS, EVEN_ROW_EVEN_COLUMN_GAIN, EVEN_ROW_ODD_COLUMN_GAIN,
+    FRAME_RESTART, GLOBAL_GAIN, ODD_ROW_EVEN_COLUMN_GAIN, ODD_ROW_ODD_COLUMN_GAIN, OUTPUT_CONTROL,
+    RESET, SENSOR_ADDRESS,
+};
+
+/// Reg0x07 bits and the Reg0xF1 bits they are copied to.
+const MIRRORED_BITS: [(u16, u16); 2] = [
+    // Synchronize changes
+    (1 << 0, 1 << 1),
+    // Chip Enable
+    (1 << 1, 1 << 0),
+];
+
+pub struct Simulator {
+    registers: [u16; 256],
+    /// Whether an address has a register.
+    used: [bool; 256],
+    /// The register the next byte is read from or written to.
+    address: u8,
+    /// The frames abandoned through Frame Restart.
+    pub frame_restarts: usize,
+    /// The registers read over I2C.
+    pub reads: usize,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// A chip in its power-up state.
+    pub fn new() -> Self {
+        let mut used = [false; 256];
+        for (address, _) in DEFAULTS {
+            used[address as usize] = true;
+        }
+        // Test Data has no default value.
+        used[crate::TEST_DATA as usize] = true;
+        let mut simulator = Self {
+            registers: [0; 256],
+            used,
+            address: 0,
+            frame_restarts: 0,
+            reads: 0,
+        };
+        simulator.restore_defaults();
+        simulator
+    }
+
+    fn restore_defaults(&mut self) {
+        for (address, value) in DEFAULTS {
+            self.registers[address as usize] = value;
+        }
+    }
+
+    /// Returns a register value without the side effects of reading it
+    /// over I2C.
+    pub fn register(&self, address: u8) -> u16 {
+        self.registers[address as usize]
+    }
+
+    /// Whether the sensor reads out frames, as set by Chip Enable.
+    pub fn is_enabled(&self) -> bool {
+        self.registers[CHIP_ENABLE as usize] & 1 != 0
+    }
+
+    /// Whether Reset holds the chip in its power-up state.
+    pub fn is_in_reset(&self) -> bool {
+        self.registers[RESET as usize] & 1 != 0
+    }
+
+    /// Writes `value` to the register at `address` as the chip would.
+    fn write_register(&mut self, address: u8, value: u16) {
+        if !self.used[address as usize] {
+            return;
+        }
+        if self.is_in_reset() && address != RESET {
+            return;
+        }
+        match address {
+            CHIP_VERSION => {}
+            FRAME_RESTART => {
+                if value & 1 != 0 {
+                    self.frame_restarts += 1;
+                }
+            }
+            RESET => {
+                self.registers[RESET as usize] = value & 1;
+                if value & 1 != 0 {
+                    self.restore_defaults();
+                    self.registers[RESET as usize] = 1;
+                }
+            }
+            OUTPUT_CONTROL => {
+                self.registers[OUTPUT_CONTROL as usize] = value;
+                self.mirror(OUTPUT_CONTROL, CHIP_ENABLE);
+            }
+            CHIP_ENABLE => {
+                self.registers[CHIP_ENABLE as usize] = value;
+                self.mirror(CHIP_ENABLE, OUTPUT_CONTROL);
+            }
+            GLOBAL_GAIN => {
+                for gain in [
+                    GLOBAL_GAIN,
+                    EVEN_ROW_EVEN_COLUMN_GAIN,
+                    EVEN_ROW_ODD_COLUMN_GAIN,
+                    ODD_ROW_EVEN_COLUMN_GAIN,
+                    ODD_ROW_ODD_COLUMN_GAIN,
+                ] {
+                    self.registers[gain as usize] = value;
+                }
+            }
+            _ => self.registers[address as usize] = value,
+        }
+    }
+
+    /// Copies the mirrored bits of the register at `from` to the one at
+    /// `to`.
+    fn mirror(&mut self, from: u8, to: u8) {
+        for (output_control, chip_enable) in MIRRORED_BITS {
+            let (from_bit, to_bit) = if from == OUTPUT_CONTROL {
+                (output_control, chip_enable)
+            } else {
+                (chip_enable, output_control)
+            };
+            let set = self.registers[from as usize] & from_bit != 0;
+            let to = &mut self.registers[to as usize];
+            if set {
+                *to |= to_bit;
+            } else {
+                *to &= !to_bit;
+            }
+        }
+    }
+}
+
+impl ErrorType for Simulator {
+    type Error = ErrorKind;
+}
+
+impl I2c for Simulator {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), ErrorKind> {
+        if address != SENSOR_ADDRESS {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    // A write starts with the register address, followed by
+                    // the register values, high byte first.
+                    let Some((&address, values)) = bytes.split_first() else {
+                        continue;
+                    };
+                    self.address = address;
+                    for value in values.chunks_exact(2) {
+                        self.write_register(self.address, u16::from_be_bytes([value[0], value[1]]));
+                        self.address = self.address.wrapping_add(1);
+                    }
+                }
+                Operation::Read(bytes) => {
+                    for pair in bytes.chunks_mut(2) {
+                        let value = self.registers[self.address as usize].to_be_bytes();
+                        pair.copy_from_slice(&value[..pair.len()]);
+                        self.reads += 1;
+                        self.address = self.address.wrapping_add(1);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        CalCtrl, OutputControl, ReadOptions1, ShutterWidth, CAL_CTRL, COLUMN_SIZE, MT9M001,
+        READ_OPTIONS_1, SHUTTER_WIDTH,
+    };
+
+    fn write(chip: &mut Simulator, address: u8, values: &[u16]) {
+        let mut bytes = [0; 9];
+        bytes[0] = address;
+        for (pair, value) in bytes[1..].chunks_exact_mut(2).zip(values) {
+            pair.copy_from_slice(&value.to_be_bytes());
+        }
+        chip.write(SENSOR_ADDRESS, &bytes[..1 + values.len() * 2])
+            .unwrap();
+    }
+
+    fn read<const N: usize>(chip: &mut Simulator, address: u8) -> [u16; N] {
+        let mut bytes = [0; 8];
+        chip.write_read(SENSOR_ADDRESS, &[address], &mut bytes[..N * 2])
+            .unwrap();
+        core::array::from_fn(|i| u16::from_be_bytes([bytes[i * 2], bytes[i * 2 + 1]]))
+    }
+
+    #[test]
+    fn registers_power_up_with_their_defaults() {
+        let mut chip = Simulator::new();
+        for (address, value) in DEFAULTS {
+            assert_eq!(read::<1>(&mut chip, address), [value], "{address:#04x}");
+        }
+        write(&mut chip, CHIP_VERSION, &[0]);
+        assert_eq!(chip.register(CHIP_VERSION), 0x8431);
+    }
+
+    #[test]
+    fn address_advances_after_every_register() {
+        let mut chip = Simulator::new();
+        write(&mut chip, COLUMN_SIZE, &[1311, 35]);
+        assert_eq!(read::<2>(&mut chip, COLUMN_SIZE), [1311, 35]);
+        assert_eq!(chip.register(COLUMN_SIZE + 1), 35);
+    }
+
+    #[test]
+    fn unused_addresses_read_zero() {
+        let mut chip = Simulator::new();
+        write(&mut chip, 0x08, &[0x1234]);
+        assert_eq!(read::<1>(&mut chip, 0x08), [0]);
+    }
+
+    #[test]
+    fn other_addresses_are_not_acknowledged() {
+        let mut chip = Simulator::new();
+        assert_eq!(
+            chip.write(SENSOR_ADDRESS + 1, &[COLUMN_SIZE]),
+            Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+        );
+    }
+
+    #[test]
+    fn reset_restores_and_holds_defaults() {
+        let mut chip = Simulator::new();
+        write(&mut chip, SHUTTER_WIDTH, &[42]);
+        write(&mut chip, RESET, &[1]);
+        assert!(chip.is_in_reset());
+        assert_eq!(
+            chip.register(SHUTTER_WIDTH),
+            ShutterWidth::DEFAULT.register()
+        );
+        write(&mut chip, SHUTTER_WIDTH, &[42]);
+        assert_eq!(
+            chip.register(SHUTTER_WIDTH),
+            ShutterWidth::DEFAULT.register()
+        );
+        write(&mut chip, RESET, &[0]);
+        write(&mut chip, SHUTTER_WIDTH, &[42]);
+        assert_eq!(chip.register(SHUTTER_WIDTH), 42);
+    }
+
+    #[test]
+    fn chip_enable_is_mirrored() {
+        let mut chip = Simulator::new();
+        assert!(chip.is_enabled());
+        let output_control = OutputControl::DEFAULT.set_chip_enable(false);
+        write(&mut chip, OUTPUT_CONTROL, &[output_control.value]);
+        assert!(!chip.is_enabled());
+        write(&mut chip, CHIP_ENABLE, &[0b11]);
+        assert!(OutputControl::new(chip.register(OUTPUT_CONTROL)).get_chip_enable());
+        assert!(OutputControl::new(chip.register(OUTPUT_CONTROL)).get_synchronize_changes());
+    }
+
+    #[test]
+    fn global_gain_sets_the_color_gains() {
+        let mut chip = Simulator::new();
+        write(&mut chip, EVEN_ROW_ODD_COLUMN_GAIN, &[0x10]);
+        write(&mut chip, GLOBAL_GAIN, &[0x20]);
+        for gain in [
+            EVEN_ROW_EVEN_COLUMN_GAIN,
+            EVEN_ROW_ODD_COLUMN_GAIN,
+            ODD_ROW_EVEN_COLUMN_GAIN,
+            ODD_ROW_ODD_COLUMN_GAIN,
+        ] {
+            assert_eq!(chip.register(gain), 0x20);
+        }
+    }
+
+    #[test]
+    fn frame_restart_clears_itself() {
+        let mut chip = Simulator::new();
+        MT9M001::new(&mut chip).restart_frame().unwrap();
+        assert_eq!(chip.frame_restarts, 1);
+        assert_eq!(chip.register(FRAME_RESTART), 0);
+    }
+
+    #[test]
+    fn cached_driver_modifies_registers() {
+        let mut chip = Simulator::new();
+        let mut mt9m001 = MT9M001::new_cached(&mut chip);
+        mt9m001
+            .modify_read_options_1(|r| r.set_snapshot_mode(true))
+            .unwrap();
+        mt9m001
+            .modify_read_options_1(|r| r.set_column_skip_8(true))
+            .unwrap();
+        assert_eq!(chip.reads, 1);
+        let expected = ReadOptions1::DEFAULT
+            .set_snapshot_mode(true)
+            .set_column_skip_8(true);
+        assert_eq!(chip.register(READ_OPTIONS_1), expected.value);
+    }
+
+    #[test]
+    fn uncached_driver_reads_every_modified_register() {
+        let mut chip = Simulator::new();
+        let mut mt9m001 = MT9M001::new(&mut chip);
+        for _ in 0..2 {
+            mt9m001
+                .modify_read_options_1(|r| r.set_snapshot_mode(true))
+                .unwrap();
+        }
+        assert_eq!(chip.reads, 2);
+    }
+
+    #[test]
+    fn chip_enable_writes_drop_the_cached_output_control() {
+        let mut chip = Simulator::new();
+        let mut mt9m001 = MT9M001::new_cached(&mut chip);
+        mt9m001.modify_output_control(|o| o).unwrap();
+        mt9m001.set_chip_enable(0b10).unwrap();
+        mt9m001.modify_output_control(|o| o).unwrap();
+        assert_eq!(chip.reads, 2);
+        assert!(!OutputControl::new(chip.register(OUTPUT_CONTROL)).get_chip_enable());
+        assert!(!chip.is_enabled());
+    }
+
+    #[test]
+    fn self_clearing_bits_are_cached_clear() {
+        let mut chip = Simulator::new();
+        let mut mt9m001 = MT9M001::new_cached(&mut chip);
+        mt9m001
+            .modify_cal_ctrl(|c| {
+                c.set_start_a_new_running_digitally_filtered_average_for_the_black_level(true)
+            })
+            .unwrap();
+        mt9m001.modify_cal_ctrl(|c| c).unwrap();
+        assert_eq!(chip.reads, 1);
+        assert_eq!(chip.register(CAL_CTRL), CalCtrl::DEFAULT.value);
+    }
+
+    #[test]
+    fn soft_reset_clears_the_cache() {
+        let mut chip = Simulator::new();
+        let mut mt9m001 = MT9M001::new_cached(&mut chip);
+        mt9m001
+            .modify_read_options_1(|r| r.set_snapshot_mode(true))
+            .unwrap();
+        mt9m001.soft_reset().unwrap();
+        mt9m001
+            .modify_read_options_1(|r| r.set_column_skip_8(true))
+            .unwrap();
+        assert_eq!(chip.reads, 2);
+        let expected = ReadOptions1::DEFAULT.set_column_skip_8(true);
+        assert_eq!(chip.register(READ_OPTIONS_1), expected.value);
+    }
+}
@@ -0,0 +1,223 @@
+//! Checks that the driver `build.rs` generates keeps the API of the
+//! hand-checked `lib.rs` it replaced, recorded in `hand_checked_api.txt`.
+
+use std::collections::BTreeSet;
+
+const GENERATED: &str = include_str!(concat!(env!("OUT_DIR"), "/mt9m001.rs"));
+const HAND_CHECKED: &str = include_str!("hand_checked_api.txt");
+
+/// Functions the register access modes and value types replaced on
+/// purpose, and what replaced them.
+const REPLACED: [(&str, Option<&str>); 11] = [
+    // Chip Version is read-only.
+    (
+        "fn MT9M001::set_chip_version(&mut self, value: u16) -> Result<(), I2C::Error>",
+        None,
+    ),
+    // Frame Restart and Reset are commands.
+    (
+        "fn MT9M001::get_frame_restart(&mut self) -> Result<u16, I2C::Error>",
+        None,
+    ),
+    (
+        "fn MT9M001::set_frame_restart(&mut self, value: u16) -> Result<(), I2C::Error>",
+        Some("fn MT9M001::restart_frame(&mut self) -> Result<(), I2C::Error>"),
+    ),
+    (
+        "fn MT9M001::get_reset(&mut self) -> Result<u16, I2C::Error>",
+        None,
+    ),
+    (
+        "fn MT9M001::set_reset(&mut self, value: u16) -> Result<(), I2C::Error>",
+        Some("fn MT9M001::soft_reset(&mut self) -> Result<(), I2C::Error>"),
+    ),
+    // Strobe Width is an enum.
+    (
+        "fn ReadOptions1::set_strobe_width(self, value: bool) -> Self",
+        Some("fn ReadOptions1::set_strobe_width(self, value: StrobeWidth) -> Self"),
+    ),
+    (
+        "fn ReadOptions1::get_strobe_width(&self) -> bool",
+        Some("fn ReadOptions1::get_strobe_width(&self) -> StrobeWidth"),
+    ),
+    // The thresholds are narrower than u16 and checked.
+    (
+        "fn CalThreshold::set_thres_lo(self, value: u16) -> Self",
+        Some("fn CalThreshold::set_thres_lo(self, value: u8) -> Result<Self, InvalidValue>"),
+    ),
+    (
+        "fn CalThreshold::get_thres_lo(&self) -> u16",
+        Some("fn CalThreshold::get_thres_lo(&self) -> u8"),
+    ),
+    (
+        "fn CalThreshold::set_thres_hi(self, value: u16) -> Self",
+        Some("fn CalThreshold::set_thres_hi(self, value: u8) -> Result<Self, InvalidValue>"),
+    ),
+    (
+        "fn CalThreshold::get_thres_hi(&self) -> u16",
+        Some("fn CalThreshold::get_thres_hi(&self) -> u8"),
+    ),
+];
+
+/// Registers whose `u16` accessors take and return a value type instead.
+const VALUE_TYPES: [(&str, &str); 18] = [
+    ("row_start", "RowStart"),
+    ("column_start", "ColumnStart"),
+    ("row_size", "RowSize"),
+    ("column_size", "ColumnSize"),
+    ("horizontal_blanking", "HorizontalBlanking"),
+    ("vertical_blanking", "VerticalBlanking"),
+    ("shutter_width", "ShutterWidth"),
+    ("shutter_delay", "ShutterDelay"),
+    ("even_row_even_column_gain", "Gain"),
+    ("odd_row_even_column_gain", "Gain"),
+    ("even_row_odd_column_gain", "Gain"),
+    ("odd_row_odd_column_gain", "Gain"),
+    ("test_data", "TestData"),
+    ("global_gain", "Gain"),
+    ("even_row_even_column_analog_offset", "AnalogOffset"),
+    ("odd_row_odd_column_analog_offset", "AnalogOffset"),
+    ("even_row_odd_column_analog_offset", "AnalogOffset"),
+    ("odd_row_even_column_analog_offset", "AnalogOffset"),
+];
+
+/// Lists the register addresses, power-up defaults and function
+/// signatures of the driver source `source` like `hand_checked_api.txt`
+/// does.
+fn api(source: &str) -> BTreeSet<String> {
+    let mut api = BTreeSet::new();
+    let mut impl_type = None;
+    let mut signature: Option<String> = None;
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if let Some(mut pending) = signature.take() {
+            // A signature continues up to its body or `where` clause.
+            pending.push(' ');
+            pending.push_str(trimmed);
+            signature = Some(pending);
+        } else if let Some(header) = line.strip_prefix("impl") {
+            // `impl Type {` or `impl<I2C> Type<I2C>`
+            let header = header.split_once("> ").map_or(header, |(_, rest)| rest);
+            let name = header.trim_start().split(['<', ' ']).next().unwrap();
+            impl_type = Some(name.to_owned());
+        } else if let Some(register) = trimmed
+            .strip_prefix("pub const ")
+            .filter(|register| register.contains(": u8 = 0x"))
+        {
+            api.extend(register.split_once(": u8 = ").map(|(name, address)| {
+                format!("register {name} {}", address.trim_end_matches(';'))
+            }));
+        } else if let Some(default) = trimmed.strip_prefix('(') {
+            api.extend(
+                default
+                    .trim_end_matches("),")
+                    .split_once(", 0x")
+                    .map(|(name, value)| format!("default {name} 0x{value}")),
+            );
+        } else if let Some(function) = trimmed
+            .strip_prefix("pub const fn ")
+            .or_else(|| trimmed.strip_prefix("pub fn "))
+        {
+            signature = impl_type
+                .as_ref()
+                .map(|impl_type| format!("fn {impl_type}::{function}"));
+        }
+        if let Some(pending) = signature.take() {
+            match pending
+                .strip_suffix('{')
+                .or_else(|| pending.strip_suffix("where"))
+            {
+                Some(complete) => {
+                    api.insert(normalize(complete));
+                }
+                None => signature = Some(pending),
+            }
+        }
+    }
+    api
+}
+
+/// Writes a signature on one line the way `hand_checked_api.txt` does,
+/// without the `mut` of a `mut self` receiver.
+fn normalize(signature: &str) -> String {
+    signature
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("( ", "(")
+        .replace(", )", ")")
+        .replace("(mut self", "(self")
+}
+
+/// `REPLACED` together with the value type accessors of `VALUE_TYPES`.
+fn replaced() -> Vec<(String, Option<String>)> {
+    let mut replaced: Vec<_> = REPLACED
+        .iter()
+        .map(|(old, new)| (old.to_string(), new.map(str::to_owned)))
+        .collect();
+    for (register, value_type) in VALUE_TYPES {
+        replaced.push((
+            format!("fn MT9M001::get_{register}(&mut self) -> Result<u16, I2C::Error>"),
+            Some(format!(
+                "fn MT9M001::get_{register}(&mut self) -> Result<{value_type}, I2C::Error>"
+            )),
+        ));
+        replaced.push((
+            format!("fn MT9M001::set_{register}(&mut self, value: u16) -> Result<(), I2C::Error>"),
+            Some(format!(
+                "fn MT9M001::set_{register}(&mut self, value: {value_type}) -> Result<(), I2C::Error>"
+            )),
+        ));
+    }
+    replaced
+}
+
+fn hand_checked() -> BTreeSet<String> {
+    HAND_CHECKED
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[test]
+fn registers_and_defaults_match() {
+    let generated = api(GENERATED);
+    let is_data = |item: &&String| !item.starts_with("fn ");
+    let generated: BTreeSet<_> = generated.iter().filter(is_data).collect();
+    let hand_checked = hand_checked();
+    let hand_checked: BTreeSet<_> = hand_checked.iter().filter(is_data).collect();
+    assert!(!hand_checked.is_empty());
+    assert_eq!(generated, hand_checked);
+}
+
+#[test]
+fn hand_checked_accessors_are_generated() {
+    let generated = api(GENERATED);
+    let replaced = replaced();
+    let missing: Vec<_> = hand_checked()
+        .into_iter()
+        .filter(|item| !generated.contains(item))
+        .filter(|item| !replaced.iter().any(|(old, _)| item == old))
+        .collect();
+    assert!(missing.is_empty(), "not generated: {missing:#?}");
+}
+
+#[test]
+fn replaced_accessors_are_gone() {
+    let generated = api(GENERATED);
+    let hand_checked = hand_checked();
+    for (replaced, replacement) in replaced() {
+        assert!(
+            hand_checked.contains(&replaced),
+            "{replaced} was not hand-checked"
+        );
+        assert!(
+            !generated.contains(&replaced),
+            "{replaced} is still generated"
+        );
+        if let Some(replacement) = replacement {
+            assert!(generated.contains(&replacement), "{replacement} is missing");
+        }
+    }
+}